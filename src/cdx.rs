@@ -0,0 +1,732 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+};
+
+use arrow::{
+    array::{Array, ArrayRef, AsArray, StringArray, UInt32Array, UInt64Array},
+    compute::{cast, concat_batches, interleave, lexsort_to_indices, take, SortColumn},
+    datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit, TimestampSecondType, UInt16Type},
+    ipc::{reader::StreamReader, writer::StreamWriter},
+    record_batch::RecordBatch,
+};
+use lazy_static::lazy_static;
+use time::OffsetDateTime;
+
+use crate::{column::WarcColumn, error::WarcParquetError, schema::UTC};
+
+type CdxResult<T> = Result<T, WarcParquetError>;
+
+/// The line heading a classic CDX index, naming its eleven fields.
+const CDX_HEADER: &str = " CDX N b a m s k r M S V g";
+
+/// The number of bytes of entries [`CdxSorter`] holds in memory by default.
+const DEFAULT_MAX_BUFFERED_BYTES: usize = 256 * 1024 * 1024;
+
+/// The number of entries in each record batch [`CdxSorter`] writes and
+/// returns.
+const SORTED_BATCH_ROWS: usize = 8192;
+
+lazy_static! {
+    /// The schema of a CDX index, with a field for each field of a CDXJ line.
+    ///
+    /// The `urlkey` is the record's target URI in SURT form and the
+    /// `timestamp` its date as the fourteen digits `YYYYMMDDhhmmss`. The
+    /// `length` and `offset` locate the record in the WARC file named by
+    /// `filename`.
+    pub static ref CDX_SCHEMA: SchemaRef = Arc::new(Schema::new(vec![
+        Field::new("urlkey", DataType::Utf8, false),
+        Field::new("timestamp", DataType::Utf8, false),
+        Field::new("url", DataType::Utf8, false),
+        Field::new("mime", DataType::Utf8, true),
+        Field::new("status", DataType::UInt16, true),
+        Field::new("digest", DataType::Utf8, true),
+        Field::new("length", DataType::UInt64, false),
+        Field::new("offset", DataType::UInt64, false),
+        Field::new("filename", DataType::Utf8, true),
+    ]));
+}
+
+/// The text formats a CDX index may be written in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CdxFormat {
+    /// CDXJ, as written by `pywb`: the URL key and timestamp followed by a
+    /// JSON object of the remaining fields, e.g.
+    /// `com,example)/ 20200708025255 {"url": "http://example.com/", ...}`.
+    /// Fields which are null are left out of the object.
+    #[default]
+    Cdxj,
+
+    /// Classic CDX with the eleven fields `N b a m s k r M S V g`, headed by
+    /// a line naming them. Fields which are null, along with the redirect and
+    /// meta tags fields, are written as `-`.
+    Cdx,
+}
+
+impl CdxFormat {
+    /// Writes the line heading an index of this format, if it has one.
+    pub fn write_header<W: Write + ?Sized>(&self, writer: &mut W) -> CdxResult<()> {
+        if let CdxFormat::Cdx = self {
+            writeln!(writer, "{CDX_HEADER}")?;
+        }
+        Ok(())
+    }
+
+    /// Writes each row of a record batch of [`CDX_SCHEMA`](static@CDX_SCHEMA)
+    /// as a line of this format.
+    pub fn write_lines<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        record_batch: &RecordBatch,
+    ) -> CdxResult<()> {
+        let string_column = |name: &str| -> CdxResult<&StringArray> {
+            column(record_batch, name)?
+                .as_string_opt()
+                .ok_or_else(|| WarcParquetError::UnknownField(name.to_string()))
+        };
+        let u64_column = |name: &str| -> CdxResult<&UInt64Array> {
+            column(record_batch, name)?
+                .as_any()
+                .downcast_ref()
+                .ok_or_else(|| WarcParquetError::UnknownField(name.to_string()))
+        };
+        let urlkeys = string_column("urlkey")?;
+        let timestamps = string_column("timestamp")?;
+        let urls = string_column("url")?;
+        let mimes = string_column("mime")?;
+        let statuses = column(record_batch, "status")?
+            .as_primitive_opt::<UInt16Type>()
+            .ok_or_else(|| WarcParquetError::UnknownField(String::from("status")))?;
+        let digests = string_column("digest")?;
+        let lengths = u64_column("length")?;
+        let offsets = u64_column("offset")?;
+        let filenames = string_column("filename")?;
+
+        for row in 0..record_batch.num_rows() {
+            let value = |array| string_value(array, row);
+            let status = statuses.is_valid(row).then(|| statuses.value(row));
+            match self {
+                CdxFormat::Cdxj => {
+                    let mut fields = vec![("url", json_string(urls.value(row)))];
+                    if let Some(mime) = value(mimes) {
+                        fields.push(("mime", json_string(mime)));
+                    }
+                    if let Some(status) = status {
+                        fields.push(("status", json_string(&status.to_string())));
+                    }
+                    if let Some(digest) = value(digests) {
+                        fields.push(("digest", json_string(digest)));
+                    }
+                    fields.push(("length", json_string(&lengths.value(row).to_string())));
+                    fields.push(("offset", json_string(&offsets.value(row).to_string())));
+                    if let Some(filename) = value(filenames) {
+                        fields.push(("filename", json_string(filename)));
+                    }
+
+                    let object = fields
+                        .iter()
+                        .map(|(name, value)| format!("\"{name}\": {value}"))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    writeln!(
+                        writer,
+                        "{} {} {{{object}}}",
+                        urlkeys.value(row),
+                        timestamps.value(row)
+                    )?;
+                }
+                CdxFormat::Cdx => {
+                    writeln!(
+                        writer,
+                        "{} {} {} {} {} {} - - {} {} {}",
+                        urlkeys.value(row),
+                        timestamps.value(row),
+                        urls.value(row),
+                        value(mimes).unwrap_or("-"),
+                        status.map_or_else(|| String::from("-"), |status| status.to_string()),
+                        value(digests).unwrap_or("-"),
+                        lengths.value(row),
+                        offsets.value(row),
+                        value(filenames).unwrap_or("-"),
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Builds CDX index entries from record batches read by a
+/// [`WarcToArrowReader`](crate::WarcToArrowReader) with the columns
+/// [`CdxIndexer::COLUMNS`].
+///
+/// Only `response`, `revisit` and `resource` records are indexed unless
+/// [`with_all_records`](CdxIndexer::with_all_records) is set, and records
+/// without a target URI are never indexed. Entries are as `pywb`'s
+/// `cdx-indexer` writes them:
+///
+/// - the `mime` of a record holding an HTTP message is its `Content-Type`,
+///   less any parameters, or `unk` if it has none. That of a `revisit` record
+///   is `warc/revisit` and that of any other record its own `Content-Type`.
+/// - the `digest` is the record's `WARC-Payload-Digest`, less any `sha1:`
+///   prefix.
+/// - the `filename` is the name of the WARC file, without its directory.
+///
+/// # Example
+///
+/// ```rust
+/// use std::io::{BufReader, Cursor};
+///
+/// use warc_parquet::{CdxFormat, CdxIndexer, WarcToArrowReader};
+///
+/// # fn main() {
+/// let warc_content = b"\
+///     WARC/1.1\r\n\
+///     WARC-Type: response\r\n\
+///     Content-Length: 47\r\n\
+///     WARC-Record-Id: <urn:test:cdx:record-0>\r\n\
+///     WARC-Date: 2020-07-08T02:52:55Z\r\n\
+///     WARC-Target-URI: http://www.example.com/\r\n\
+///     WARC-Payload-Digest: sha1:3I42H3S6NNFQ2MSVX7XZKYAYSCX5QBYJ\r\n\
+///     Content-Type: application/http; msgtype=response\r\n\
+///     \r\n\
+///     HTTP/1.1 200 OK\r\n\
+///     Content-Type: text/html\r\n\
+///     \r\n\
+///     Hi!\r\n\
+///     \r\n\
+///     \r\n\
+/// ";
+///
+/// let input = BufReader::new(Cursor::new(warc_content));
+/// let mut reader = WarcToArrowReader::builder(input)
+///     .with_columns(CdxIndexer::COLUMNS)
+///     .with_source_filename("crawl/example.warc")
+///     .build()
+///     .unwrap();
+/// let indexer = CdxIndexer::new();
+///
+/// let mut index = Vec::new();
+/// for record_batch in reader.iter_reader() {
+///     let cdx_batch = indexer.index(&record_batch.unwrap()).unwrap();
+///     CdxFormat::Cdxj.write_lines(&mut index, &cdx_batch).unwrap();
+/// }
+///
+/// assert_eq!(
+///     String::from_utf8(index).unwrap(),
+///     "com,example)/ 20200708025255 {\"url\": \"http://www.example.com/\", \
+///      \"mime\": \"text/html\", \"status\": \"200\", \
+///      \"digest\": \"3I42H3S6NNFQ2MSVX7XZKYAYSCX5QBYJ\", \"length\": \"332\", \
+///      \"offset\": \"0\", \"filename\": \"example.warc\"}\n"
+/// );
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CdxIndexer {
+    all_records: bool,
+}
+
+impl CdxIndexer {
+    /// The columns the record batches given to [`index`](CdxIndexer::index)
+    /// must have.
+    pub const COLUMNS: &'static [WarcColumn] = &[
+        WarcColumn::UrlSurt,
+        WarcColumn::Date,
+        WarcColumn::TargetUri,
+        WarcColumn::Type,
+        WarcColumn::ContentType,
+        WarcColumn::HttpStatus,
+        WarcColumn::HttpContentType,
+        WarcColumn::PayloadDigest,
+        WarcColumn::WarcLength,
+        WarcColumn::WarcOffset,
+        WarcColumn::WarcFilename,
+    ];
+
+    /// Creates an indexer of `response`, `revisit` and `resource` records.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether records of every type, e.g. `request` and `metadata`, are
+    /// indexed.
+    pub fn with_all_records(mut self, all_records: bool) -> Self {
+        self.all_records = all_records;
+        self
+    }
+
+    /// Returns a record batch of [`CDX_SCHEMA`](static@CDX_SCHEMA) holding an
+    /// entry for each record of the batch which is indexed, in the order read.
+    pub fn index(&self, record_batch: &RecordBatch) -> CdxResult<RecordBatch> {
+        let string_column = |column: WarcColumn| -> CdxResult<&StringArray> {
+            record_batch
+                .column_by_name(column.name())
+                .and_then(|array| array.as_string_opt())
+                .ok_or_else(|| WarcParquetError::UnknownField(column.name().to_string()))
+        };
+        let urlkeys = string_column(WarcColumn::UrlSurt)?;
+        let target_uris = string_column(WarcColumn::TargetUri)?;
+        let types = string_column(WarcColumn::Type)?;
+        let content_types = string_column(WarcColumn::ContentType)?;
+        let http_content_types = string_column(WarcColumn::HttpContentType)?;
+        let payload_digests = string_column(WarcColumn::PayloadDigest)?;
+        let warc_filenames = string_column(WarcColumn::WarcFilename)?;
+        let http_statuses = column(record_batch, WarcColumn::HttpStatus.name())?;
+        let warc_lengths = column(record_batch, WarcColumn::WarcLength.name())?;
+        let warc_offsets = column(record_batch, WarcColumn::WarcOffset.name())?;
+        let dates = cast(
+            column(record_batch, WarcColumn::Date.name())?,
+            &DataType::Timestamp(TimeUnit::Second, Some(UTC.into())),
+        )?;
+        let dates = dates.as_primitive::<TimestampSecondType>();
+
+        let rows = (0..record_batch.num_rows())
+            .filter(|&row| {
+                let is_indexed_type = types.is_valid(row)
+                    && (self.all_records
+                        || matches!(types.value(row), "response" | "revisit" | "resource"));
+                is_indexed_type
+                    && target_uris.is_valid(row)
+                    && dates.is_valid(row)
+                    && warc_lengths.is_valid(row)
+                    && warc_offsets.is_valid(row)
+            })
+            .map(|row| row as u32)
+            .collect::<Vec<_>>();
+
+        let timestamps = rows
+            .iter()
+            .map(|&row| {
+                OffsetDateTime::from_unix_timestamp(dates.value(row as usize))
+                    .ok()
+                    .map(|date| {
+                        format!(
+                            "{:04}{:02}{:02}{:02}{:02}{:02}",
+                            date.year(),
+                            u8::from(date.month()),
+                            date.day(),
+                            date.hour(),
+                            date.minute(),
+                            date.second()
+                        )
+                    })
+            })
+            .collect::<StringArray>();
+        let mimes = rows
+            .iter()
+            .map(|&row| {
+                let row = row as usize;
+                let is_http = http_statuses.is_valid(row) || http_content_types.is_valid(row);
+                let mime = if types.value(row) == "revisit" {
+                    Some("warc/revisit")
+                } else if is_http {
+                    Some(string_value(http_content_types, row).unwrap_or("unk"))
+                } else {
+                    string_value(content_types, row)
+                };
+                mime.map(|mime| mime.split(';').next().unwrap_or_default().trim())
+            })
+            .collect::<StringArray>();
+        let digests = rows
+            .iter()
+            .map(|&row| {
+                string_value(payload_digests, row as usize)
+                    .map(|digest| digest.strip_prefix("sha1:").unwrap_or(digest))
+            })
+            .collect::<StringArray>();
+        let filenames = rows
+            .iter()
+            .map(|&row| {
+                string_value(warc_filenames, row as usize).map(|filename| {
+                    Path::new(filename)
+                        .file_name()
+                        .map_or(filename.into(), |file_name| file_name.to_string_lossy())
+                })
+            })
+            .collect::<StringArray>();
+
+        let indices = UInt32Array::from(rows);
+        Ok(RecordBatch::try_new(
+            CDX_SCHEMA.clone(),
+            vec![
+                take(urlkeys, &indices, None)?,
+                Arc::new(timestamps),
+                take(target_uris, &indices, None)?,
+                Arc::new(mimes),
+                take(http_statuses, &indices, None)?,
+                Arc::new(digests),
+                take(warc_lengths, &indices, None)?,
+                take(warc_offsets, &indices, None)?,
+                Arc::new(filenames),
+            ],
+        )?)
+    }
+}
+
+/// Concatenates record batches of [`CDX_SCHEMA`](static@CDX_SCHEMA) into one
+/// sorted by URL key and then timestamp, the order in which an index may be
+/// searched.
+///
+/// Every entry is held in memory at once, so indexes too large for that are
+/// better sorted by a [`CdxSorter`].
+pub fn sort_cdx(record_batches: &[RecordBatch]) -> CdxResult<RecordBatch> {
+    let record_batch = concat_batches(&CDX_SCHEMA, record_batches)?;
+    let sort_columns = ["urlkey", "timestamp"]
+        .map(|name| {
+            Ok(SortColumn {
+                values: column(&record_batch, name)?.clone(),
+                options: None,
+            })
+        })
+        .into_iter()
+        .collect::<CdxResult<Vec<_>>>()?;
+    let indices = lexsort_to_indices(&sort_columns, None)?;
+    let columns = record_batch
+        .columns()
+        .iter()
+        .map(|column| take(column, &indices, None))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(RecordBatch::try_new(CDX_SCHEMA.clone(), columns)?)
+}
+
+/// Sorts CDX index entries as [`sort_cdx`] does, holding only so many in
+/// memory at once.
+///
+/// Entries are buffered until they exceed a limit, at which point they are
+/// sorted and written to a run file in a directory. Once every entry has been
+/// pushed, the runs are merged as the sorted entries are read. Run files are
+/// removed once the merge is dropped.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use arrow::{
+///     array::{StringArray, UInt64Array},
+///     compute::concat_batches,
+///     record_batch::RecordBatch,
+/// };
+/// use tempfile::tempdir;
+/// use warc_parquet::{sort_cdx, CdxSorter, CDX_SCHEMA};
+///
+/// # fn main() {
+/// let entries = |urlkeys: &[&str]| {
+///     let rows = urlkeys.len();
+///     RecordBatch::try_new(
+///         CDX_SCHEMA.clone(),
+///         vec![
+///             Arc::new(StringArray::from(urlkeys.to_vec())),
+///             Arc::new(StringArray::from(vec!["20200708025255"; rows])),
+///             Arc::new(StringArray::from(vec!["http://example.com/"; rows])),
+///             Arc::new(StringArray::from(vec![None::<&str>; rows])),
+///             Arc::new(arrow::array::UInt16Array::from(vec![None; rows])),
+///             Arc::new(StringArray::from(vec![None::<&str>; rows])),
+///             Arc::new(UInt64Array::from(vec![0; rows])),
+///             Arc::new(UInt64Array::from(vec![0; rows])),
+///             Arc::new(StringArray::from(vec![None::<&str>; rows])),
+///         ],
+///     )
+///     .unwrap()
+/// };
+/// let record_batches = [
+///     entries(&["com,example)/c", "com,example)/a"]),
+///     entries(&["org,example)/", "com,example)/b"]),
+///     entries(&["net,example)/"]),
+/// ];
+///
+/// // With no room to buffer entries, each batch is written as a run of its
+/// // own.
+/// let directory = tempdir().unwrap();
+/// let mut sorter = CdxSorter::new(directory.path()).with_max_buffered_bytes(0);
+/// for record_batch in &record_batches {
+///     sorter.push(record_batch.clone()).unwrap();
+/// }
+/// let sorted = sorter.finish().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+///
+/// assert_eq!(
+///     concat_batches(&CDX_SCHEMA, &sorted).unwrap(),
+///     sort_cdx(&record_batches).unwrap()
+/// );
+/// # }
+/// ```
+pub struct CdxSorter {
+    directory: PathBuf,
+    max_buffered_bytes: usize,
+    buffered: Vec<RecordBatch>,
+    buffered_bytes: usize,
+    runs: Vec<RunFile>,
+}
+
+impl CdxSorter {
+    /// Creates a sorter which writes its run files to the directory.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            max_buffered_bytes: DEFAULT_MAX_BUFFERED_BYTES,
+            buffered: Vec::new(),
+            buffered_bytes: 0,
+            runs: Vec::new(),
+        }
+    }
+
+    /// Sets the most bytes of entries held in memory before they are written
+    /// to a run file, 256 MiB by default.
+    pub fn with_max_buffered_bytes(mut self, max_buffered_bytes: usize) -> Self {
+        self.max_buffered_bytes = max_buffered_bytes;
+        self
+    }
+
+    /// Adds the entries of a record batch of [`CDX_SCHEMA`](static@CDX_SCHEMA).
+    pub fn push(&mut self, record_batch: RecordBatch) -> CdxResult<()> {
+        if record_batch.num_rows() == 0 {
+            return Ok(());
+        }
+        self.buffered_bytes += record_batch.get_array_memory_size();
+        self.buffered.push(record_batch);
+        if self.buffered_bytes > self.max_buffered_bytes {
+            self.write_run()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the entries pushed, sorted, in record batches of up to 8192
+    /// entries.
+    pub fn finish(mut self) -> CdxResult<SortedCdx> {
+        // Entries which were never written out need not be.
+        if self.runs.is_empty() {
+            let sorted = sort_cdx(&self.buffered)?;
+            let batches = (0..sorted.num_rows())
+                .step_by(SORTED_BATCH_ROWS)
+                .map(|offset| {
+                    sorted.slice(offset, SORTED_BATCH_ROWS.min(sorted.num_rows() - offset))
+                })
+                .collect::<Vec<_>>();
+            return Ok(SortedCdx::Buffered(batches.into_iter()));
+        }
+
+        if !self.buffered.is_empty() {
+            self.write_run()?;
+        }
+        let mut cursors = Vec::with_capacity(self.runs.len());
+        let mut heap = BinaryHeap::with_capacity(self.runs.len());
+        for run in &self.runs {
+            let reader = StreamReader::try_new(File::open(&run.path)?, None)?;
+            let mut cursor = RunCursor {
+                reader,
+                record_batch: None,
+                row: 0,
+            };
+            if let Some(key) = cursor.advance()? {
+                heap.push(Reverse((key, cursors.len())));
+            }
+            cursors.push(cursor);
+        }
+
+        Ok(SortedCdx::Merged(CdxMerge {
+            cursors,
+            heap,
+            _runs: self.runs,
+        }))
+    }
+
+    /// Sorts the buffered entries and writes them to a new run file.
+    fn write_run(&mut self) -> CdxResult<()> {
+        let sorted = sort_cdx(&self.buffered)?;
+        self.buffered.clear();
+        self.buffered_bytes = 0;
+
+        let (run, file) = RunFile::create(&self.directory)?;
+        self.runs.push(run);
+        let mut writer = StreamWriter::try_new(BufWriter::new(file), &CDX_SCHEMA)?;
+        for offset in (0..sorted.num_rows()).step_by(SORTED_BATCH_ROWS) {
+            let rows = SORTED_BATCH_ROWS.min(sorted.num_rows() - offset);
+            writer.write(&sorted.slice(offset, rows))?;
+        }
+        writer.finish()?;
+        writer.into_inner()?.flush()?;
+        Ok(())
+    }
+}
+
+/// The sorted entries of a [`CdxSorter`], in record batches of
+/// [`CDX_SCHEMA`](static@CDX_SCHEMA).
+pub enum SortedCdx {
+    /// Entries which were sorted in memory.
+    Buffered(std::vec::IntoIter<RecordBatch>),
+
+    /// Entries merged from run files as they are read.
+    Merged(CdxMerge),
+}
+
+impl Iterator for SortedCdx {
+    type Item = CdxResult<RecordBatch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            SortedCdx::Buffered(batches) => batches.next().map(Ok),
+            SortedCdx::Merged(merge) => merge.next_batch().transpose(),
+        }
+    }
+}
+
+/// A merge of the sorted run files of a [`CdxSorter`].
+pub struct CdxMerge {
+    cursors: Vec<RunCursor>,
+    /// The key of the next entry of each run not yet exhausted, least first.
+    /// Ties go to the earlier run, keeping entries in the order pushed.
+    heap: BinaryHeap<Reverse<((String, String), usize)>>,
+    /// Held so that the run files are removed once the merge is dropped.
+    _runs: Vec<RunFile>,
+}
+
+impl CdxMerge {
+    fn next_batch(&mut self) -> CdxResult<Option<RecordBatch>> {
+        // The record batches entries are taken from, and for each run the
+        // index among them of its current batch, once it has been added.
+        let mut sources: Vec<RecordBatch> = Vec::new();
+        let mut source_of_run: Vec<Option<usize>> = vec![None; self.cursors.len()];
+        let mut indices = Vec::with_capacity(SORTED_BATCH_ROWS);
+
+        while indices.len() < SORTED_BATCH_ROWS {
+            let Some(Reverse((_, run))) = self.heap.pop() else {
+                break;
+            };
+            let cursor = &mut self.cursors[run];
+            let record_batch = cursor
+                .record_batch
+                .as_ref()
+                .expect("runs in the heap have a current batch");
+            let source = *source_of_run[run].get_or_insert_with(|| {
+                sources.push(record_batch.clone());
+                sources.len() - 1
+            });
+            indices.push((source, cursor.row));
+
+            cursor.row += 1;
+            if cursor.row == record_batch.num_rows() {
+                source_of_run[run] = None;
+            }
+            if let Some(key) = cursor.advance()? {
+                self.heap.push(Reverse((key, run)));
+            }
+        }
+
+        if indices.is_empty() {
+            return Ok(None);
+        }
+        let columns = (0..CDX_SCHEMA.fields().len())
+            .map(|index| {
+                let arrays = sources
+                    .iter()
+                    .map(|record_batch| record_batch.column(index).as_ref())
+                    .collect::<Vec<_>>();
+                interleave(&arrays, &indices)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Some(RecordBatch::try_new(CDX_SCHEMA.clone(), columns)?))
+    }
+}
+
+/// A position in a run file being merged.
+struct RunCursor {
+    reader: StreamReader<BufReader<File>>,
+    record_batch: Option<RecordBatch>,
+    row: usize,
+}
+
+impl RunCursor {
+    /// Moves on to the next batch of the run should the current one be
+    /// exhausted, returning the key of the entry at the cursor, or `None` once
+    /// the run is.
+    fn advance(&mut self) -> CdxResult<Option<(String, String)>> {
+        while self
+            .record_batch
+            .as_ref()
+            .is_none_or(|record_batch| self.row >= record_batch.num_rows())
+        {
+            match self.reader.next().transpose()? {
+                Some(record_batch) => {
+                    self.record_batch = Some(record_batch);
+                    self.row = 0;
+                }
+                None => {
+                    self.record_batch = None;
+                    return Ok(None);
+                }
+            }
+        }
+
+        let record_batch = self.record_batch.as_ref().expect("a batch was read");
+        let key = |name| -> CdxResult<String> {
+            Ok(column(record_batch, name)?
+                .as_string::<i32>()
+                .value(self.row)
+                .to_string())
+        };
+        Ok(Some((key("urlkey")?, key("timestamp")?)))
+    }
+}
+
+/// A run file of a [`CdxSorter`], removed once dropped.
+struct RunFile {
+    path: PathBuf,
+}
+
+impl RunFile {
+    /// Creates a run file in the directory, given a name no other file holds.
+    fn create(directory: &Path) -> io::Result<(Self, File)> {
+        let mut attempt = 0;
+        loop {
+            let path = directory.join(format!(".cdx-{}-{attempt}.arrow", process::id()));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => return Ok((Self { path }, file)),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => attempt += 1,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl Drop for RunFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Returns the named column of the record batch.
+fn column<'a>(record_batch: &'a RecordBatch, name: &str) -> CdxResult<&'a ArrayRef> {
+    record_batch
+        .column_by_name(name)
+        .ok_or_else(|| WarcParquetError::UnknownField(name.to_string()))
+}
+
+/// Returns the value of a row of a string array, or `None` if it is null.
+fn string_value(array: &StringArray, row: usize) -> Option<&str> {
+    array.is_valid(row).then(|| array.value(row))
+}
+
+/// Returns the value as a JSON string.
+fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
@@ -0,0 +1,411 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    thread,
+};
+
+use arrow::record_batch::RecordBatch;
+use parquet::{arrow::ArrowWriter, file::properties::WriterProperties};
+
+use crate::{
+    error::WarcParquetError,
+    output::{AtomicFile, ParquetSink},
+    partition::{PartitionKey, PartitionedParquetWriter},
+    reader::{WarcToArrowReader, WarcToArrowReaderBuilder},
+};
+
+type ConvertResult<T> = Result<T, WarcParquetError>;
+
+/// The buffer capacity used when reading inputs.
+const INPUT_BUFFER_CAPACITY: usize = 1_048_576;
+
+/// Where [`convert_many`] writes its Parquet.
+#[derive(Clone, Debug)]
+pub enum ConvertOutput {
+    /// Write one Parquet file per input into the directory, named after the
+    /// input with `.parquet` appended, e.g. `example.warc.gz.parquet`.
+    PerInput(PathBuf),
+
+    /// Merge the inputs into one Parquet file per worker in the directory,
+    /// named `part-00000.parquet`, `part-00001.parquet` and so on.
+    Merged(PathBuf),
+
+    /// Merge the inputs into a Hive-style partitioned dataset in the
+    /// directory, as written by [`PartitionedParquetWriter`]. Each worker
+    /// writes its own files in each partition, named after it, e.g.
+    /// `type=response/part-00000-00001.parquet`.
+    Partitioned {
+        /// The root directory of the dataset.
+        directory: PathBuf,
+        /// The keys partitioned by, in the order of the directory levels.
+        keys: Vec<PartitionKey>,
+    },
+}
+
+impl ConvertOutput {
+    /// Returns the directory written to.
+    pub fn directory(&self) -> &Path {
+        match self {
+            ConvertOutput::PerInput(directory)
+            | ConvertOutput::Merged(directory)
+            | ConvertOutput::Partitioned { directory, .. } => directory,
+        }
+    }
+}
+
+/// The writer of one worker's output.
+enum OutputWriter {
+    File(PathBuf, ArrowWriter<AtomicFile>),
+    Partitioned(PartitionedParquetWriter),
+}
+
+impl OutputWriter {
+    /// Completes the output, returning the paths of the files written.
+    fn close(self) -> ConvertResult<Vec<PathBuf>> {
+        match self {
+            OutputWriter::File(path, writer) => {
+                writer.into_inner()?.commit()?;
+                Ok(vec![path])
+            }
+            OutputWriter::Partitioned(writer) => writer.close(),
+        }
+    }
+}
+
+impl ParquetSink for OutputWriter {
+    fn write(&mut self, record_batch: &RecordBatch) -> ConvertResult<()> {
+        match self {
+            OutputWriter::File(_, writer) => ParquetSink::write(writer, record_batch),
+            OutputWriter::Partitioned(writer) => writer.write(record_batch),
+        }
+    }
+
+    fn flush(&mut self) -> ConvertResult<()> {
+        match self {
+            OutputWriter::File(_, writer) => ParquetSink::flush(writer),
+            OutputWriter::Partitioned(writer) => writer.flush(),
+        }
+    }
+}
+
+/// Determines how inputs are handed out to workers when they are merged.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConvertOrder {
+    /// Split the inputs into contiguous runs, one per worker, so that each
+    /// output file holds the same inputs in the order given however long each
+    /// takes to convert.
+    #[default]
+    Input,
+
+    /// Hand the next input to whichever worker is free first. This balances
+    /// uneven inputs better, however which output file an input lands in, and
+    /// in what order, varies from run to run.
+    Completion,
+}
+
+/// Options for [`convert_many`] and [`write_parquet`].
+#[derive(Clone, Debug)]
+pub struct ConvertOptions {
+    /// The number of inputs converted at once. Defaults to the available
+    /// parallelism.
+    pub jobs: usize,
+
+    /// How inputs are handed out to workers when merging.
+    pub order: ConvertOrder,
+
+    /// The properties of the Parquet written.
+    pub writer_properties: WriterProperties,
+
+    /// Close a row group after each record batch, rather than buffering
+    /// batches into row groups of the writer's maximum row group size.
+    pub flush_batches: bool,
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        Self {
+            jobs: thread::available_parallelism().map_or(1, usize::from),
+            order: ConvertOrder::default(),
+            writer_properties: WriterProperties::default(),
+            flush_batches: false,
+        }
+    }
+}
+
+/// Tallies of a conversion.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConvertSummary {
+    /// The Parquet files written, in the order of the inputs they begin with.
+    pub outputs: Vec<PathBuf>,
+
+    /// The number of malformed records skipped.
+    pub records_skipped: u64,
+
+    /// The number of malformed records quarantined.
+    pub records_quarantined: u64,
+
+    /// The number of malformed fields set to null.
+    pub fields_nulled: u64,
+
+    /// The number of records whose block or payload digest does not match.
+    pub digest_mismatches: u64,
+}
+
+impl ConvertSummary {
+    fn add(&mut self, other: ConvertSummary) {
+        self.outputs.extend(other.outputs);
+        self.records_skipped += other.records_skipped;
+        self.records_quarantined += other.records_quarantined;
+        self.fields_nulled += other.fields_nulled;
+        self.digest_mismatches += other.digest_mismatches;
+    }
+}
+
+/// Writes record batches, e.g. those of an [`IterReader`](crate::IterReader)
+/// or a [`ParallelReader`](crate::ParallelReader), to the writer, whose schema
+/// must be the batches'.
+pub fn write_parquet<S: ParquetSink + ?Sized>(
+    writer: &mut S,
+    record_batches: impl Iterator<Item = ConvertResult<RecordBatch>>,
+    options: &ConvertOptions,
+) -> ConvertResult<()> {
+    for record_batch in record_batches {
+        let record_batch = record_batch?;
+        writer.write(&record_batch)?;
+        if options.flush_batches {
+            writer.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts many WARC files to Parquet concurrently, on
+/// [`ConvertOptions::jobs`] threads.
+///
+/// Each input is read by a reader from `configure`, which is handed a builder
+/// over the file with its [source filename](WarcToArrowReaderBuilder::with_source_filename)
+/// already set. Every reader must produce the same schema.
+///
+/// Conversion stops at the first input which fails, returning a
+/// [`WarcParquetError::Input`] naming it. Output files are written as
+/// [`AtomicFile`]s, so those completed before then are left in place while
+/// those in progress are removed.
+///
+/// # Example
+///
+/// ```rust
+/// use std::fs;
+///
+/// use tempfile::tempdir;
+/// use warc_parquet::{convert_many, ConvertOptions, ConvertOutput, WarcColumn};
+///
+/// # fn main() {
+/// let warc_content = b"\
+///     WARC/1.0\r\n\
+///     WARC-Type: resource\r\n\
+///     Content-Length: 0\r\n\
+///     WARC-Record-Id: <urn:test:convert-many:record-0>\r\n\
+///     WARC-Date: 2020-07-08T02:52:55Z\r\n\
+///     \r\n\
+///     \r\n\
+///     \r\n\
+/// ";
+///
+/// let directory = tempdir().unwrap();
+/// let inputs = ["a.warc", "b.warc", "c.warc"].map(|name| directory.path().join(name));
+/// for input in &inputs {
+///     fs::write(input, warc_content).unwrap();
+/// }
+///
+/// let output_directory = directory.path().join("parquet");
+/// let options = ConvertOptions {
+///     jobs: 2,
+///     ..Default::default()
+/// };
+/// let summary = convert_many(
+///     &inputs,
+///     &ConvertOutput::PerInput(output_directory.clone()),
+///     &options,
+///     |builder| Ok(builder.with_columns(&[WarcColumn::Id, WarcColumn::WarcFilename])),
+/// )
+/// .unwrap();
+///
+/// assert_eq!(summary.outputs.len(), 3);
+/// assert_eq!(summary.outputs[0], output_directory.join("a.warc.parquet"));
+/// # }
+/// ```
+pub fn convert_many<F>(
+    inputs: &[PathBuf],
+    output: &ConvertOutput,
+    options: &ConvertOptions,
+    configure: F,
+) -> ConvertResult<ConvertSummary>
+where
+    F: Fn(
+            WarcToArrowReaderBuilder<BufReader<File>>,
+        ) -> ConvertResult<WarcToArrowReaderBuilder<BufReader<File>>>
+        + Sync,
+{
+    if let ConvertOutput::PerInput(_) = output {
+        check_output_names(inputs)?;
+    }
+    fs::create_dir_all(output.directory())?;
+
+    let jobs = options.jobs.clamp(1, inputs.len().max(1));
+    let next_input = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+
+    let worker = |worker_index: usize| -> ConvertResult<Vec<(usize, ConvertSummary)>> {
+        // Under `ConvertOrder::Input` each worker is given a fixed run of the
+        // inputs, otherwise inputs are taken from a shared counter.
+        let run_start = worker_index * inputs.len() / jobs;
+        let run_end = (worker_index + 1) * inputs.len() / jobs;
+        let mut run = run_start..run_end;
+        let mut next = || -> Option<usize> {
+            if failed.load(Ordering::Relaxed) {
+                return None;
+            }
+            match (output, options.order) {
+                (ConvertOutput::PerInput(_), _) | (_, ConvertOrder::Completion) => {
+                    Some(next_input.fetch_add(1, Ordering::Relaxed))
+                        .filter(|&index| index < inputs.len())
+                }
+                (_, ConvertOrder::Input) => run.next(),
+            }
+        };
+
+        let convert = |index: usize,
+                       writer: &mut Option<OutputWriter>|
+         -> ConvertResult<ConvertSummary> {
+            let input = &inputs[index];
+            let stream = BufReader::with_capacity(INPUT_BUFFER_CAPACITY, File::open(input)?);
+            let builder =
+                WarcToArrowReader::builder(stream).with_source_filename(input.to_string_lossy());
+            let mut reader = configure(builder)?.build()?;
+
+            let writer = match writer {
+                Some(writer) => writer,
+                None => {
+                    let schema = reader.schema();
+                    let properties = Some(options.writer_properties.clone());
+                    let output_writer = match output {
+                        ConvertOutput::PerInput(directory) => {
+                            let path = directory.join(output_name(input));
+                            let file_writer = ArrowWriter::try_new(
+                                AtomicFile::create(&path)?,
+                                schema,
+                                properties,
+                            )?;
+                            OutputWriter::File(path, file_writer)
+                        }
+                        ConvertOutput::Merged(directory) => {
+                            let path = directory.join(format!("part-{worker_index:05}.parquet"));
+                            let file_writer = ArrowWriter::try_new(
+                                AtomicFile::create(&path)?,
+                                schema,
+                                properties,
+                            )?;
+                            OutputWriter::File(path, file_writer)
+                        }
+                        ConvertOutput::Partitioned { directory, keys } => {
+                            OutputWriter::Partitioned(
+                                PartitionedParquetWriter::try_new(
+                                    directory, schema, keys, properties,
+                                )?
+                                .with_file_stem(format!("part-{worker_index:05}")),
+                            )
+                        }
+                    };
+                    writer.insert(output_writer)
+                }
+            };
+            let mut iter_reader = reader.iter_reader();
+            write_parquet(writer, &mut iter_reader, options)?;
+            Ok(ConvertSummary {
+                outputs: Vec::new(),
+                records_skipped: iter_reader.records_skipped(),
+                records_quarantined: iter_reader.records_quarantined(),
+                fields_nulled: iter_reader.fields_nulled(),
+                digest_mismatches: iter_reader.digest_mismatches(),
+            })
+        };
+
+        let mut summaries: Vec<(usize, ConvertSummary)> = Vec::new();
+        let mut merged_writer = None;
+        while let Some(index) = next() {
+            let mut per_input_writer = None;
+            let writer = match output {
+                ConvertOutput::PerInput(_) => &mut per_input_writer,
+                _ => &mut merged_writer,
+            };
+            let mut summary = convert(index, writer).map_err(|err| {
+                failed.store(true, Ordering::Relaxed);
+                WarcParquetError::Input {
+                    path: inputs[index].clone(),
+                    source: Box::new(err),
+                }
+            })?;
+
+            if let Some(writer) = per_input_writer {
+                summary.outputs = writer.close()?;
+            }
+            summaries.push((index, summary));
+        }
+
+        // A merged output is credited to the first input written to it.
+        if let Some(writer) = merged_writer {
+            let outputs = writer.close()?;
+            if let Some((_, summary)) = summaries.first_mut() {
+                summary.outputs = outputs;
+            }
+        }
+
+        Ok(summaries)
+    };
+
+    let worker = &worker;
+    let results = thread::scope(|scope| {
+        let workers = (0..jobs)
+            .map(|worker_index| scope.spawn(move || worker(worker_index)))
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("conversion worker panicked"))
+            .collect::<Vec<_>>()
+    });
+
+    let mut summaries = Vec::new();
+    for result in results {
+        summaries.extend(result?);
+    }
+    summaries.sort_by_key(|(index, _)| *index);
+
+    let mut convert_summary = ConvertSummary::default();
+    for (_, summary) in summaries {
+        convert_summary.add(summary);
+    }
+
+    Ok(convert_summary)
+}
+
+/// Returns the name of the Parquet file written for an input.
+fn output_name(input: &Path) -> String {
+    let file_name = input.file_name().unwrap_or(input.as_os_str());
+    format!("{}.parquet", file_name.to_string_lossy())
+}
+
+/// Ensures no two inputs would be written to the same output file.
+fn check_output_names(inputs: &[PathBuf]) -> ConvertResult<()> {
+    let mut output_names = HashSet::new();
+    for input in inputs {
+        if !output_names.insert(output_name(input)) {
+            return Err(WarcParquetError::DuplicateOutput(output_name(input)));
+        }
+    }
+
+    Ok(())
+}
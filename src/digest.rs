@@ -0,0 +1,200 @@
+use md5::Md5;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+/// The algorithm record digests are computed with, for the
+/// [`ComputedBlockDigest`](crate::WarcColumn::ComputedBlockDigest) and
+/// [`ComputedPayloadDigest`](crate::WarcColumn::ComputedPayloadDigest)
+/// columns.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DigestAlgorithm {
+    /// SHA-1, as Heritrix and wget compute digests.
+    #[default]
+    Sha1,
+
+    /// SHA-256.
+    Sha256,
+
+    /// MD5.
+    Md5,
+}
+
+impl DigestAlgorithm {
+    /// Returns the algorithm a `WARC-*-Digest` label names, e.g. `sha1` or
+    /// `sha-256`, if it is supported.
+    fn from_label(label: &str) -> Option<Self> {
+        match label.to_ascii_lowercase().as_str() {
+            "sha1" | "sha-1" => Some(DigestAlgorithm::Sha1),
+            "sha256" | "sha-256" => Some(DigestAlgorithm::Sha256),
+            "md5" => Some(DigestAlgorithm::Md5),
+            _ => None,
+        }
+    }
+
+    /// Returns the label `WARC-*-Digest` values computed with the algorithm
+    /// begin with.
+    fn label(&self) -> &'static str {
+        match self {
+            DigestAlgorithm::Sha1 => "sha1",
+            DigestAlgorithm::Sha256 => "sha256",
+            DigestAlgorithm::Md5 => "md5",
+        }
+    }
+
+    /// Returns the digest of the data.
+    fn digest(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            DigestAlgorithm::Sha1 => Sha1::digest(data).to_vec(),
+            DigestAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+            DigestAlgorithm::Md5 => Md5::digest(data).to_vec(),
+        }
+    }
+}
+
+/// The encoding computed record digests are written in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DigestEncoding {
+    /// Uppercase base32, as Heritrix and wget write digests.
+    #[default]
+    Base32,
+
+    /// Lowercase hexadecimal.
+    Hex,
+
+    /// Base64, with padding.
+    Base64,
+}
+
+/// Returns the digest of the data as a `WARC-*-Digest` value, given as
+/// `algorithm:value`, e.g. `sha1:` followed by 32 base32 digits.
+pub(crate) fn compute(algorithm: DigestAlgorithm, encoding: DigestEncoding, data: &[u8]) -> String {
+    let digest = algorithm.digest(data);
+    let value = match encoding {
+        DigestEncoding::Base32 => encode_base32(&digest),
+        DigestEncoding::Hex => digest.iter().map(|byte| format!("{byte:02x}")).collect(),
+        DigestEncoding::Base64 => encode_base64(&digest),
+    };
+    format!("{}:{value}", algorithm.label())
+}
+
+/// Returns whether a `WARC-*-Digest` value, given as `algorithm:value`,
+/// matches the data, or `None` if the algorithm is not supported.
+///
+/// The value may be encoded in base32, as Heritrix and wget write it, or in
+/// hexadecimal or base64. A value which cannot be decoded never matches.
+pub(crate) fn verify(labelled_digest: &str, data: &[u8]) -> Option<bool> {
+    let (label, value) = labelled_digest.trim().split_once(':')?;
+    let algorithm = DigestAlgorithm::from_label(label)?;
+    let expected = decode_digest(value, algorithm.digest(&[]).len());
+    Some(expected.is_some_and(|expected| expected == algorithm.digest(data)))
+}
+
+/// Decodes a digest of `len` bytes, telling its encoding from its length.
+fn decode_digest(value: &str, len: usize) -> Option<Vec<u8>> {
+    let value = value.trim();
+    // The padded base32 encoding of an MD5 digest is as long as its hex
+    // encoding, but padding is not a hex digit.
+    if value.len() == len * 2 && value.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        decode_hex(value)
+    } else if value.trim_end_matches('=').len() == (len * 8).div_ceil(5) {
+        decode_base32(value)
+    } else if value.trim_end_matches('=').len() == (len * 4).div_ceil(3) {
+        decode_base64(value)
+    } else {
+        None
+    }
+}
+
+/// Encodes base32 per RFC 4648, in uppercase and with padding.
+fn encode_base32(data: &[u8]) -> String {
+    encode_bits(data, b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567", 8)
+}
+
+/// Encodes base64 per RFC 4648, in its standard alphabet and with padding.
+fn encode_base64(data: &[u8]) -> String {
+    encode_bits(
+        data,
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/",
+        4,
+    )
+}
+
+/// Encodes data in an alphabet of a power of two symbols, padding the result
+/// to a multiple of `block` symbols.
+fn encode_bits(data: &[u8], alphabet: &[u8], block: usize) -> String {
+    let bits = alphabet.len().trailing_zeros();
+    let mask = alphabet.len() - 1;
+
+    let mut encoded = String::with_capacity((data.len() * 8).div_ceil(bits as usize) + block);
+    let mut buffer = 0u32;
+    let mut buffered = 0;
+    for &byte in data {
+        buffer = (buffer << 8) | u32::from(byte);
+        buffered += 8;
+        while buffered >= bits {
+            buffered -= bits;
+            encoded.push(alphabet[(buffer >> buffered) as usize & mask] as char);
+        }
+        buffer &= (1 << buffered) - 1;
+    }
+    if buffered > 0 {
+        encoded.push(alphabet[(buffer << (bits - buffered)) as usize & mask] as char);
+    }
+    while !encoded.len().is_multiple_of(block) {
+        encoded.push('=');
+    }
+    encoded
+}
+
+/// Decodes hexadecimal, in either case.
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    value
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).ok()?;
+            u8::from_str_radix(pair, 16).ok()
+        })
+        .collect()
+}
+
+/// Decodes base32 per RFC 4648, in either case and with or without padding.
+fn decode_base32(value: &str) -> Option<Vec<u8>> {
+    decode_bits(value, 5, |byte| match byte {
+        b'A'..=b'Z' => Some(byte - b'A'),
+        b'a'..=b'z' => Some(byte - b'a'),
+        b'2'..=b'7' => Some(byte - b'2' + 26),
+        _ => None,
+    })
+}
+
+/// Decodes base64 per RFC 4648, in either its standard or URL-safe alphabet
+/// and with or without padding.
+fn decode_base64(value: &str) -> Option<Vec<u8>> {
+    decode_bits(value, 6, |byte| match byte {
+        b'A'..=b'Z' => Some(byte - b'A'),
+        b'a'..=b'z' => Some(byte - b'a' + 26),
+        b'0'..=b'9' => Some(byte - b'0' + 52),
+        b'+' | b'-' => Some(62),
+        b'/' | b'_' => Some(63),
+        _ => None,
+    })
+}
+
+/// Decodes an encoding of `bits` bits per symbol, ignoring trailing padding
+/// and any leftover bits.
+fn decode_bits(value: &str, bits: u32, symbol: impl Fn(u8) -> Option<u8>) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(value.len() * bits as usize / 8);
+    let mut buffer = 0u32;
+    let mut buffered = 0;
+    for byte in value.trim_end_matches('=').bytes() {
+        buffer = (buffer << bits) | u32::from(symbol(byte)?);
+        buffered += bits;
+        if buffered >= 8 {
+            buffered -= 8;
+            decoded.push((buffer >> buffered) as u8);
+            buffer &= (1 << buffered) - 1;
+        }
+    }
+    Some(decoded)
+}
@@ -0,0 +1,282 @@
+use std::{error::Error, fmt, io, path::PathBuf};
+
+use crate::{arrow::error::ArrowError, parquet::errors::ParquetError};
+
+/// Errors which may occur while reading WARC and translating it to Arrow or
+/// Parquet.
+///
+/// Errors concerning a single record carry the byte offset at which that record
+/// begins in the input, so that malformed records may be located in the source
+/// file.
+///
+/// # Example
+///
+/// Input which is not WARC is reported as a malformed record. Under an
+/// [`ErrorPolicy`](crate::ErrorPolicy) other than the default, reading resumes
+/// at the next line which begins a record.
+///
+/// ```rust
+/// use std::io::{BufReader, Cursor};
+///
+/// use warc_parquet::{ErrorPolicy, WarcParquetError, WarcToArrowReader};
+///
+/// # fn main() {
+/// let warc_content = b"\
+///     garbage\r\n\
+///     more garbage\r\n\
+///     WARC/1.0\r\n\
+///     WARC-Type: resource\r\n\
+///     Content-Length: 0\r\n\
+///     WARC-Record-Id: <urn:test:garbage:record-0>\r\n\
+///     WARC-Date: 2020-07-08T02:52:55Z\r\n\
+///     \r\n\
+///     \r\n\
+///     \r\n\
+/// ";
+///
+/// let input = BufReader::new(Cursor::new(warc_content));
+/// let mut reader = WarcToArrowReader::builder(input).build().unwrap();
+/// let err = reader.iter_reader().next().unwrap().unwrap_err();
+/// assert!(matches!(
+///     err,
+///     WarcParquetError::MalformedRecord {
+///         reason: "missing WARC version line",
+///         offset: 0,
+///     }
+/// ));
+///
+/// let input = BufReader::new(Cursor::new(warc_content));
+/// let mut reader = WarcToArrowReader::builder(input)
+///     .with_error_policy(ErrorPolicy::Skip)
+///     .build()
+///     .unwrap();
+/// let mut iter_reader = reader.iter_reader();
+/// let record_batch = iter_reader.next().unwrap().unwrap();
+/// assert_eq!(record_batch.num_rows(), 1);
+/// assert_eq!(iter_reader.records_skipped(), 1);
+/// # }
+/// ```
+#[derive(Debug)]
+pub enum WarcParquetError {
+    /// A header the WARC specification requires is absent from a record.
+    MissingHeader {
+        /// The name of the missing header.
+        header: String,
+        /// The byte offset of the record in the input.
+        offset: u64,
+    },
+
+    /// A header value could not be interpreted, e.g. a length which is not an
+    /// integer or a date which is not ISO 8601.
+    MalformedHeader {
+        /// The name of the offending header.
+        header: String,
+        /// The value of the header, lossily converted to UTF-8.
+        value: String,
+        /// The byte offset of the record in the input.
+        offset: u64,
+    },
+
+    /// A length header holds a value too large for the 32-bit field it is
+    /// stored in, as with schemas predating 64-bit lengths.
+    LengthOverflow {
+        /// The name of the offending header.
+        header: String,
+        /// The length the header declares.
+        value: u64,
+        /// The byte offset of the record in the input.
+        offset: u64,
+    },
+
+    /// A record is not well-formed WARC, e.g. it lacks a version line or ends
+    /// before its declared content length.
+    MalformedRecord {
+        /// A description of what is wrong with the record.
+        reason: &'static str,
+        /// The byte offset of the record in the input.
+        offset: u64,
+    },
+
+    /// A schema field does not correspond to any column the reader can
+    /// produce.
+    UnknownField(String),
+
+    /// A partition key names no column of the output, or a derived key lacks
+    /// the column it is derived from.
+    UnknownPartitionKey(String),
+
+    /// Two inputs would be converted to the Parquet file of the given name.
+    DuplicateOutput(String),
+
+    /// Converting one of several inputs failed.
+    Input {
+        /// The path of the input.
+        path: PathBuf,
+        /// The error converting it.
+        source: Box<WarcParquetError>,
+    },
+
+    /// Reading input or writing output failed, e.g. the disk is full.
+    Io(io::Error),
+
+    /// Constructing Arrow data failed.
+    Arrow(ArrowError),
+
+    /// Writing Parquet failed.
+    Parquet(ParquetError),
+}
+
+impl WarcParquetError {
+    /// Returns the byte offset of the record the error concerns, if any.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::io::{BufReader, Cursor};
+    ///
+    /// use warc_parquet::{WarcParquetError, WarcToArrowReader};
+    ///
+    /// # fn main() {
+    /// let first_record = b"\
+    ///     WARC/1.0\r\n\
+    ///     WARC-Type: resource\r\n\
+    ///     Content-Length: 2\r\n\
+    ///     WARC-Record-Id: <urn:test:offset:record-0>\r\n\
+    ///     WARC-Date: 2020-07-08T02:52:55Z\r\n\
+    ///     \r\n\
+    ///     Hi\r\n\
+    ///     \r\n\
+    /// ";
+    /// // The second record ends well short of its declared length.
+    /// let truncated_record = b"\
+    ///     WARC/1.0\r\n\
+    ///     WARC-Type: resource\r\n\
+    ///     Content-Length: 100\r\n\
+    ///     WARC-Record-Id: <urn:test:offset:record-1>\r\n\
+    ///     WARC-Date: 2020-07-08T02:52:55Z\r\n\
+    ///     \r\n\
+    ///     Hello\
+    /// ";
+    /// let warc_content = [first_record.as_slice(), truncated_record].concat();
+    ///
+    /// let input = BufReader::new(Cursor::new(warc_content));
+    /// let mut reader = WarcToArrowReader::builder(input).build().unwrap();
+    /// // The first record is returned ahead of the error.
+    /// let err = reader.iter_reader().nth(1).unwrap().unwrap_err();
+    /// assert!(matches!(
+    ///     err,
+    ///     WarcParquetError::MalformedRecord {
+    ///         reason: "unexpected end of record block",
+    ///         ..
+    ///     }
+    /// ));
+    /// assert_eq!(err.offset(), Some(first_record.len() as u64));
+    ///
+    /// // Header blocks are read into memory only up to a limit of 1 MiB.
+    /// let oversized_record = [
+    ///     b"WARC/1.0\r\nX-Padding: ".as_slice(),
+    ///     &vec![b'a'; 2 * 1024 * 1024],
+    ///     b"\r\n\r\n",
+    /// ]
+    /// .concat();
+    /// let input = BufReader::new(Cursor::new(oversized_record));
+    /// let mut reader = WarcToArrowReader::builder(input).build().unwrap();
+    /// let err = reader.iter_reader().next().unwrap().unwrap_err();
+    /// assert!(matches!(
+    ///     err,
+    ///     WarcParquetError::MalformedRecord {
+    ///         reason: "header block too large",
+    ///         offset: 0,
+    ///     }
+    /// ));
+    /// # }
+    /// ```
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            Self::MissingHeader { offset, .. }
+            | Self::MalformedHeader { offset, .. }
+            | Self::LengthOverflow { offset, .. }
+            | Self::MalformedRecord { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// Moves the byte offset the error carries, if any, forward by `delta`.
+    pub(crate) fn shift_offset(&mut self, delta: u64) {
+        match self {
+            Self::MissingHeader { offset, .. }
+            | Self::MalformedHeader { offset, .. }
+            | Self::LengthOverflow { offset, .. }
+            | Self::MalformedRecord { offset, .. } => *offset += delta,
+            _ => {}
+        }
+    }
+}
+
+impl fmt::Display for WarcParquetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingHeader { header, offset } => {
+                write!(f, "Missing {header} header in record at offset {offset}.")
+            }
+            Self::MalformedHeader {
+                header,
+                value,
+                offset,
+            } => write!(
+                f,
+                "Malformed {header} header {value:?} in record at offset {offset}."
+            ),
+            Self::LengthOverflow {
+                header,
+                value,
+                offset,
+            } => write!(
+                f,
+                "{header} of {value} in record at offset {offset} exceeds a 32-bit field."
+            ),
+            Self::MalformedRecord { reason, offset } => {
+                write!(f, "Malformed record at offset {offset}: {reason}.")
+            }
+            Self::UnknownField(name) => write!(f, "Unknown schema field {name:?}."),
+            Self::UnknownPartitionKey(name) => write!(f, "Unknown partition key {name:?}."),
+            Self::DuplicateOutput(name) => {
+                write!(f, "More than one input would be written to {name:?}.")
+            }
+            Self::Input { path, .. } => write!(f, "Error converting {}.", path.display()),
+            Self::Io(err) => write!(f, "I/O error: {err}"),
+            Self::Arrow(_) => write!(f, "Error building Arrow data."),
+            Self::Parquet(_) => write!(f, "Error writing Parquet."),
+        }
+    }
+}
+
+impl Error for WarcParquetError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Input { source, .. } => Some(source),
+            Self::Io(err) => Some(err),
+            Self::Arrow(err) => Some(err),
+            Self::Parquet(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for WarcParquetError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<ArrowError> for WarcParquetError {
+    fn from(err: ArrowError) -> Self {
+        Self::Arrow(err)
+    }
+}
+
+impl From<ParquetError> for WarcParquetError {
+    fn from(err: ParquetError) -> Self {
+        Self::Parquet(err)
+    }
+}
@@ -0,0 +1,255 @@
+use std::io::{self, Read};
+
+use libflate::{deflate, gzip, zlib};
+
+/// The line opening an HTTP message.
+pub(crate) enum StartLine {
+    /// The request line, e.g. `GET / HTTP/1.1`.
+    Request { method: String, version: String },
+
+    /// The status line, e.g. `HTTP/1.1 200 OK`.
+    Response {
+        version: String,
+        status: u16,
+        reason: String,
+    },
+}
+
+/// The head of an HTTP request or response held in a record block.
+pub(crate) struct HttpMessage {
+    /// The request or status line.
+    pub(crate) start_line: StartLine,
+    /// The header fields of the message, in the order they appear.
+    pub(crate) headers: Vec<(String, Vec<u8>)>,
+    /// The offset in the record block at which the message body begins.
+    pub(crate) payload_offset: usize,
+}
+
+impl HttpMessage {
+    /// Parses the head of an HTTP message from the start of a record block.
+    ///
+    /// Bare line feeds are accepted in place of CRLF, as are messages which end
+    /// without the blank line terminating their head. Returns a description of
+    /// the problem if the block does not begin with an HTTP message.
+    pub(crate) fn parse(block: &[u8]) -> Result<Self, &'static str> {
+        let mut lines = Lines { block, position: 0 };
+
+        let start_line = lines
+            .next()
+            .ok_or("missing HTTP start line")
+            .and_then(parse_start_line)?;
+
+        let mut headers: Vec<(String, Vec<u8>)> = Vec::new();
+        for line in lines.by_ref() {
+            if line.is_empty() {
+                break;
+            }
+
+            // Obsolete line folding continues the previous header.
+            if line[0] == b' ' || line[0] == b'\t' {
+                let (_, value) = headers
+                    .last_mut()
+                    .ok_or("HTTP continuation line without a header")?;
+                value.push(b' ');
+                value.extend_from_slice(line.trim_ascii());
+                continue;
+            }
+
+            let colon = line
+                .iter()
+                .position(|&b| b == b':')
+                .ok_or("HTTP header line without a colon")?;
+            let name = String::from_utf8_lossy(line[..colon].trim_ascii()).into_owned();
+            let value = line[colon + 1..].trim_ascii().to_vec();
+            headers.push((name, value));
+        }
+
+        Ok(Self {
+            start_line,
+            headers,
+            payload_offset: lines.position,
+        })
+    }
+
+    /// Returns the raw value of the first header with the given name. Names are
+    /// compared case-insensitively.
+    pub(crate) fn header(&self, name: &str) -> Option<&[u8]> {
+        self.headers
+            .iter()
+            .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_slice())
+    }
+
+    /// Removes the transfer and content codings the message declares from its
+    /// body, returning `None` if it declares none.
+    ///
+    /// Fails if a coding is unsupported, the body cannot be decoded, or the
+    /// decoded body would exceed `limit` bytes.
+    pub(crate) fn decode_body(&self, body: &[u8], limit: usize) -> io::Result<Option<Vec<u8>>> {
+        // Content codings are applied before transfer codings, and each in the
+        // order listed, so they are removed in reverse.
+        let mut codings: Vec<String> = self
+            .codings("Content-Encoding")
+            .chain(self.codings("Transfer-Encoding"))
+            .filter(|coding| coding != "identity")
+            .collect();
+        if codings.is_empty() || body.is_empty() {
+            return Ok(None);
+        }
+
+        let mut decoded = body.to_vec();
+        while let Some(coding) = codings.pop() {
+            decoded = match coding.as_str() {
+                "chunked" => dechunk(&decoded)?,
+                "gzip" | "x-gzip" => read_limited(gzip::Decoder::new(decoded.as_slice())?, limit)?,
+                // Despite the specification calling for zlib framing, servers
+                // commonly send raw deflate data.
+                "deflate" => zlib::Decoder::new(decoded.as_slice())
+                    .and_then(|decoder| read_limited(decoder, limit))
+                    .or_else(|_| read_limited(deflate::Decoder::new(decoded.as_slice()), limit))?,
+                "br" => read_limited(brotli::Decompressor::new(decoded.as_slice(), 4096), limit)?,
+                "zstd" => read_limited(zstd::Decoder::new(decoded.as_slice())?, limit)?,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        format!("unsupported HTTP coding {coding:?}"),
+                    ))
+                }
+            };
+        }
+
+        if decoded.len() > limit {
+            return Err(limit_exceeded());
+        }
+        Ok(Some(decoded))
+    }
+
+    /// Returns the lowercased codings listed by the named header.
+    fn codings<'a>(&'a self, name: &'a str) -> impl Iterator<Item = String> + 'a {
+        self.headers
+            .iter()
+            .filter(move |(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .flat_map(|(_, value)| value.split(|&b| b == b','))
+            .map(|coding| String::from_utf8_lossy(coding.trim_ascii()).to_ascii_lowercase())
+            .filter(|coding| !coding.is_empty())
+    }
+
+    /// Returns the version of the message, e.g. `HTTP/1.1`.
+    pub(crate) fn version(&self) -> &str {
+        match &self.start_line {
+            StartLine::Request { version, .. } | StartLine::Response { version, .. } => version,
+        }
+    }
+}
+
+fn parse_start_line(line: &[u8]) -> Result<StartLine, &'static str> {
+    let line = std::str::from_utf8(line).map_err(|_| "HTTP start line is not UTF-8")?;
+
+    if line.starts_with("HTTP/") {
+        let mut parts = line.splitn(3, ' ');
+        let version = parts.next().unwrap_or_default();
+        let status = parts
+            .next()
+            .and_then(|status| status.parse().ok())
+            .ok_or("malformed HTTP status code")?;
+        let reason = parts.next().unwrap_or_default().trim();
+
+        return Ok(StartLine::Response {
+            version: version.to_string(),
+            status,
+            reason: reason.to_string(),
+        });
+    }
+
+    let mut parts = line.split_ascii_whitespace();
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(_target), Some(version), None) if version.starts_with("HTTP/") => {
+            Ok(StartLine::Request {
+                method: method.to_string(),
+                version: version.to_string(),
+            })
+        }
+        _ => Err("malformed HTTP start line"),
+    }
+}
+
+/// Removes chunked transfer coding from a body. Trailer fields following the
+/// last chunk are discarded.
+fn dechunk(body: &[u8]) -> io::Result<Vec<u8>> {
+    let malformed = || io::Error::new(io::ErrorKind::InvalidData, "malformed chunked body");
+
+    let mut dechunked = Vec::with_capacity(body.len());
+    let mut lines = Lines {
+        block: body,
+        position: 0,
+    };
+    loop {
+        let size_line = lines.next().ok_or_else(malformed)?;
+        // Chunk extensions follow the size and are of no interest.
+        let size = size_line
+            .split(|&b| b == b';')
+            .next()
+            .and_then(|size| std::str::from_utf8(size.trim_ascii()).ok())
+            .and_then(|size| usize::from_str_radix(size, 16).ok())
+            .ok_or_else(malformed)?;
+        if size == 0 {
+            return Ok(dechunked);
+        }
+
+        let chunk = body
+            .get(lines.position..lines.position.saturating_add(size))
+            .ok_or_else(malformed)?;
+        dechunked.extend_from_slice(chunk);
+        lines.position += size;
+
+        // The line ending closing the chunk.
+        lines.next().ok_or_else(malformed)?;
+    }
+}
+
+/// Reads the decoder to the end, failing if it yields more than `limit` bytes.
+fn read_limited<D: Read>(decoder: D, limit: usize) -> io::Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    decoder.take(limit as u64 + 1).read_to_end(&mut decoded)?;
+    if decoded.len() > limit {
+        return Err(limit_exceeded());
+    }
+    Ok(decoded)
+}
+
+fn limit_exceeded() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "decoded HTTP body exceeds limit",
+    )
+}
+
+/// Splits a block into lines, tracking the offset following the last line
+/// returned.
+struct Lines<'a> {
+    block: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = &self.block[self.position..];
+        if rest.is_empty() {
+            return None;
+        }
+
+        let line = match rest.iter().position(|&b| b == b'\n') {
+            Some(line_feed) => {
+                self.position += line_feed + 1;
+                &rest[..line_feed]
+            }
+            None => {
+                self.position = self.block.len();
+                rest
+            }
+        };
+        Some(line.strip_suffix(b"\r").unwrap_or(line))
+    }
+}
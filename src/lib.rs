@@ -1,4 +1,3 @@
-#![deny(missing_docs)]
 //! A crate providing a reader from Web ARChive (WARC) to Apache Arrow format.
 //!
 //! Users will create a [`WarcToArrowReader`] over a WARC source. This source is
@@ -7,20 +6,28 @@
 //! consumers can iterate over records by calling
 //! [`iter_reader`](WarcToArrowReader::iter_reader).
 //!
-//! Internally, the reader uses [`WarcReader`](warc::WarcReader) to read from
-//! the provided source. More specifically the streaming interface provided by
-//! `WarcReader` is used. This allows the reader to consume very large or
-//! indefinite streams. The reader also provides a facility for reading the WARC
+//! Internally, the reader consumes the provided source one record at a time.
+//! This allows the reader to consume very large or indefinite streams. The
+//! reader also provides a facility for reading the WARC
 //! records into batches of a given `batch_size` (this is useful for forming row
 //! groups, e.g. with Parquet). These batches become
 //! [`RecordBatch`](arrow::record_batch::RecordBatch).
 //!
+//! Malformed input is reported as a [`WarcParquetError`] rather than a panic.
+//! Errors concerning a particular record carry the byte offset of that record
+//! in the source. Rather than stopping at the first malformed record, the
+//! reader may instead be configured to skip, null out, or quarantine such
+//! records via an [`ErrorPolicy`].
+//!
 //! Once translated to Arrow, consumers may operate on the output however they
 //! like. For use cases involving Parquet, the `warc-parquet` command line
 //! utility is provided.
 //!
 //! Currently this crate provides a schema for WARC Format 1.0 as
-//! [`WARC_1_0_SCHEMA`](static@WARC_1_0_SCHEMA).
+//! [`WARC_1_0_SCHEMA`](static@WARC_1_0_SCHEMA) and for WARC Format 1.1 as
+//! [`WARC_1_1_SCHEMA`](static@WARC_1_1_SCHEMA). The version of each record is
+//! detected as it is read, so archives mixing both versions may be converted
+//! using the latter schema.
 //!
 //! # Example
 //!
@@ -49,7 +56,8 @@
 //!
 //! let mut reader = WarcToArrowReader::builder(input)
 //!     .with_batch_size(1024)
-//!     .build();
+//!     .build()
+//!     .unwrap();
 //! let mut writer = ArrowWriter::try_new(&mut output, WARC_1_0_SCHEMA.clone(), None).unwrap();
 //!
 //! let record_batches = reader.iter_reader();
@@ -91,9 +99,37 @@
 #![forbid(unsafe_code)]
 
 pub use arrow;
+pub use cdx::{sort_cdx, CdxFormat, CdxIndexer, CdxMerge, CdxSorter, SortedCdx, CDX_SCHEMA};
+pub use column::WarcColumn;
+pub use convert::{
+    convert_many, write_parquet, ConvertOptions, ConvertOrder, ConvertOutput, ConvertSummary,
+};
+pub use digest::{DigestAlgorithm, DigestEncoding};
+pub use error::WarcParquetError;
+pub use output::{AtomicFile, ParquetSink, RollingParquetWriter};
+pub use parallel::ParallelReader;
 pub use parquet;
-pub use reader::{WarcToArrowReader, WarcToArrowReaderBuilder};
-pub use schema::WARC_1_0_SCHEMA;
+pub use partition::{PartitionKey, PartitionedParquetWriter};
+pub use reader::{
+    BodyPolicy, ErrorPolicy, IterReader, WarcToArrowReader, WarcToArrowReaderBuilder,
+};
+pub use schema::{UTC, WARC_1_0_SCHEMA, WARC_1_1_SCHEMA};
+pub use source::{WarcCompression, WarcSource};
+pub use url::surt;
 
+mod cdx;
+mod column;
+mod convert;
+mod digest;
+mod error;
+mod http;
+mod output;
+mod parallel;
+mod partition;
+mod psl;
 mod reader;
+mod record;
 mod schema;
+mod source;
+mod spill;
+mod url;
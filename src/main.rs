@@ -1,14 +1,23 @@
 use std::{
-    fs::OpenOptions,
-    io::{self, BufRead, BufReader, Write},
-    path::PathBuf,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use clap::{Parser, ValueEnum};
-use libflate::gzip::MultiDecoder as GzipReader;
 use warc_parquet::{
+    arrow::{
+        datatypes::{DataType, Schema, SchemaRef, TimeUnit},
+        record_batch::RecordBatch,
+    },
+    convert_many,
     parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties},
-    WarcToArrowReader, WARC_1_0_SCHEMA,
+    write_parquet, AtomicFile, BodyPolicy, CdxFormat, CdxIndexer, CdxSorter, ConvertOptions,
+    ConvertOrder, ConvertOutput, ConvertSummary, DigestAlgorithm, DigestEncoding, ErrorPolicy,
+    ParallelReader, ParquetSink, PartitionKey, RollingParquetWriter, WarcColumn, WarcCompression,
+    WarcParquetError, WarcToArrowReader, WarcToArrowReaderBuilder, CDX_SCHEMA, UTC,
+    WARC_1_0_SCHEMA, WARC_1_1_SCHEMA,
 };
 
 const MB: usize = 1_048_576;
@@ -39,6 +48,191 @@ impl From<OptCompression> for Compression {
     }
 }
 
+#[derive(ValueEnum, Clone, Debug)]
+enum OptSchema {
+    #[clap(name = "warc-1.0")]
+    Warc1_0,
+    #[clap(name = "warc-1.1")]
+    Warc1_1,
+}
+
+impl From<OptSchema> for SchemaRef {
+    fn from(opt_schema: OptSchema) -> Self {
+        match opt_schema {
+            OptSchema::Warc1_0 => WARC_1_0_SCHEMA.clone(),
+            OptSchema::Warc1_1 => WARC_1_1_SCHEMA.clone(),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum OptTimeUnit {
+    Second,
+    Millisecond,
+    Microsecond,
+    Nanosecond,
+}
+
+impl From<OptTimeUnit> for TimeUnit {
+    fn from(opt_time_unit: OptTimeUnit) -> Self {
+        match opt_time_unit {
+            OptTimeUnit::Second => TimeUnit::Second,
+            OptTimeUnit::Millisecond => TimeUnit::Millisecond,
+            OptTimeUnit::Microsecond => TimeUnit::Microsecond,
+            OptTimeUnit::Nanosecond => TimeUnit::Nanosecond,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum OptInputCompression {
+    Auto,
+    Uncompressed,
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl From<OptInputCompression> for Option<WarcCompression> {
+    fn from(opt_input_compression: OptInputCompression) -> Self {
+        match opt_input_compression {
+            OptInputCompression::Auto => None,
+            OptInputCompression::Uncompressed => Some(WarcCompression::Uncompressed),
+            OptInputCompression::Gzip => Some(WarcCompression::Gzip),
+            OptInputCompression::Zstd => Some(WarcCompression::Zstd),
+            OptInputCompression::Bzip2 => Some(WarcCompression::Bzip2),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum OptOrder {
+    Input,
+    Completion,
+}
+
+impl From<OptOrder> for ConvertOrder {
+    fn from(opt_order: OptOrder) -> Self {
+        match opt_order {
+            OptOrder::Input => ConvertOrder::Input,
+            OptOrder::Completion => ConvertOrder::Completion,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum OptIndex {
+    Cdxj,
+    Cdx,
+    Parquet,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum OptDigestAlgorithm {
+    Sha1,
+    Sha256,
+    Md5,
+}
+
+impl From<OptDigestAlgorithm> for DigestAlgorithm {
+    fn from(opt_digest_algorithm: OptDigestAlgorithm) -> Self {
+        match opt_digest_algorithm {
+            OptDigestAlgorithm::Sha1 => DigestAlgorithm::Sha1,
+            OptDigestAlgorithm::Sha256 => DigestAlgorithm::Sha256,
+            OptDigestAlgorithm::Md5 => DigestAlgorithm::Md5,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum OptDigestEncoding {
+    Base32,
+    Hex,
+    Base64,
+}
+
+impl From<OptDigestEncoding> for DigestEncoding {
+    fn from(opt_digest_encoding: OptDigestEncoding) -> Self {
+        match opt_digest_encoding {
+            OptDigestEncoding::Base32 => DigestEncoding::Base32,
+            OptDigestEncoding::Hex => DigestEncoding::Hex,
+            OptDigestEncoding::Base64 => DigestEncoding::Base64,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum OptErrorPolicy {
+    Fail,
+    Skip,
+    NullOut,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum OptHeaderType {
+    Utf8,
+    Int32,
+    Int64,
+    Uint32,
+    Uint64,
+    Float64,
+    Boolean,
+    Timestamp,
+    Binary,
+}
+
+impl From<OptHeaderType> for DataType {
+    fn from(opt_header_type: OptHeaderType) -> Self {
+        match opt_header_type {
+            OptHeaderType::Utf8 => DataType::Utf8,
+            OptHeaderType::Int32 => DataType::Int32,
+            OptHeaderType::Int64 => DataType::Int64,
+            OptHeaderType::Uint32 => DataType::UInt32,
+            OptHeaderType::Uint64 => DataType::UInt64,
+            OptHeaderType::Float64 => DataType::Float64,
+            OptHeaderType::Boolean => DataType::Boolean,
+            OptHeaderType::Timestamp => {
+                DataType::Timestamp(TimeUnit::Millisecond, Some(UTC.into()))
+            }
+            OptHeaderType::Binary => DataType::Binary,
+        }
+    }
+}
+
+/// A header to be promoted to a column of its own.
+#[derive(Clone, Debug)]
+struct HeaderColumn {
+    header: String,
+    name: String,
+    data_type: DataType,
+}
+
+/// Parses a header column given as `HEADER[:NAME[:TYPE]]`. The name defaults
+/// to the header in snake case and the type to `utf8`.
+fn parse_header_column(arg: &str) -> Result<HeaderColumn, String> {
+    let mut parts = arg.splitn(3, ':');
+    let header = parts.next().unwrap_or_default();
+    if header.is_empty() {
+        return Err(String::from("header must not be empty"));
+    }
+
+    let name = match parts.next() {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => header.to_ascii_lowercase().replace('-', "_"),
+    };
+
+    let data_type = match parts.next() {
+        Some(data_type) => OptHeaderType::from_str(data_type, true)?.into(),
+        None => DataType::Utf8,
+    };
+
+    Ok(HeaderColumn {
+        header: header.to_string(),
+        name,
+        data_type,
+    })
+}
+
 /// A utility for converting WARC to Parquet.
 ///
 /// WARC may be provided either as a path to a WARC file or via STDIN. Parquet
@@ -46,26 +240,42 @@ impl From<OptCompression> for Compression {
 ///
 /// With a provided path:
 ///
-///     $ warc-parquet example.warc.gz --gzipped > example.zstd.parquet
+///     $ warc-parquet example.warc.gz > example.zstd.parquet
 ///
 /// Alternatively using STDIN:
 ///
-///     $ cat example.warc.gz | gzip -d | warc-parquet > example.zstd.parquet
+///     $ cat example.warc.gz | warc-parquet > example.zstd.parquet
+///
+/// Compressed WARC, whether gzip, Zstandard or bzip2, is detected and
+/// decompressed automatically.
 ///
 /// Various compression formats for the Parquet output are also supported:
 ///
-///     $ cat example.warc.gz | warc-parquet --gzipped --compression gzip >
+///     $ cat example.warc.gz | warc-parquet --compression gzip >
 /// example.br.parquet
 #[derive(Parser, Debug)]
 #[clap(version)]
 struct Args {
-    /// Set if the WARC input is compressed with gzip.
-    #[clap(long)]
+    /// The compression of the WARC input, detected from the input by default.
+    #[clap(long, value_enum, value_parser, default_value_t = OptInputCompression::Auto)]
+    input_compression: OptInputCompression,
+
+    /// Set if the WARC input is compressed with gzip. Equivalent to
+    /// `--input-compression gzip`.
+    #[clap(long, conflicts_with = "input_compression")]
     gzipped: bool,
 
-    /// WARC input provided either as a path or via STDIN.
+    /// WARC inputs provided either as paths or via STDIN. Each is
+    /// decompressed independently, so inputs of differing compression may be
+    /// mixed.
     #[clap(default_value = STDIN_MARKER, value_parser)]
-    warc_input: PathBuf,
+    warc_input: Vec<PathBuf>,
+
+    /// Add a `source_file` column naming the input each record was read from,
+    /// null for STDIN. Implied when more than one input is written to the same
+    /// output.
+    #[clap(long)]
+    source_file: bool,
 
     /// The compression used for the Parquet.
     #[clap(short, long, value_enum, value_parser, default_value_t = OptCompression::Zstd)]
@@ -79,56 +289,636 @@ struct Args {
     /// time.
     #[clap(long, value_enum, value_parser, default_value = "4096")]
     batch_size: usize,
+
+    /// Sets the maximum number of bytes of WARC records to read at a time, so
+    /// that a batch, and the row group it is written to, closes once either
+    /// limit is reached.
+    #[clap(long, value_parser)]
+    max_batch_bytes: Option<usize>,
+
+    /// The schema of the Parquet output. The WARC 1.1 schema also accommodates
+    /// WARC 1.0 records.
+    #[clap(long, value_enum, value_parser, default_value = "warc-1.0")]
+    schema: OptSchema,
+
+    /// Include every header of each WARC record, including those the schema
+    /// does not otherwise capture, in a `headers` column.
+    #[clap(long)]
+    headers: bool,
+
+    /// Parse the HTTP messages held by request and response records into
+    /// `http_*` columns and a `payload` column holding the HTTP body.
+    #[clap(long)]
+    http: bool,
+
+    /// Add `warc_offset` and `warc_length` columns locating each record in the
+    /// WARC input, and a `warc_filename` column naming the input. For compressed
+    /// input these refer to the member holding each record.
+    #[clap(long)]
+    offsets: bool,
+
+    /// Add `url_scheme`, `url_host`, `url_port`, `url_path`, `url_query`,
+    /// `url_registered_domain` and `url_surt` columns derived from each
+    /// record's target URI.
+    #[clap(long)]
+    url: bool,
+
+    /// Add a `decoded_payload` column holding the HTTP body with chunking and
+    /// content encoding removed, and a `payload_decode_failed` column flagging
+    /// bodies that could not be decoded.
+    #[clap(long)]
+    decode_payload: bool,
+
+    /// Verify each record's block and payload digests, flagging those that do
+    /// not match in `block_digest_valid` and `payload_digest_valid` columns.
+    /// SHA-1, SHA-256 and MD5 digests are verified.
+    #[clap(long)]
+    verify_digests: bool,
+
+    /// Compute each record's block and payload digests into
+    /// `computed_block_digest` and `computed_payload_digest` columns, whether
+    /// or not the record carries digests of its own.
+    #[clap(long)]
+    compute_digests: bool,
+
+    /// The algorithm `--compute-digests` computes digests with.
+    #[clap(long, value_enum, value_parser, default_value_t = OptDigestAlgorithm::Sha1)]
+    digest_algorithm: OptDigestAlgorithm,
+
+    /// The encoding `--compute-digests` writes digests in. Heritrix writes
+    /// SHA-1 digests in base32.
+    #[clap(long, value_enum, value_parser, default_value_t = OptDigestEncoding::Base32)]
+    digest_encoding: OptDigestEncoding,
+
+    /// The size in bytes past which decoding an HTTP body is abandoned.
+    #[clap(long, value_parser, default_value = "67108864")]
+    max_decoded_payload_bytes: usize,
+
+    /// Promote a header to a column of its own, given as
+    /// `HEADER[:NAME[:TYPE]]`, e.g. `WARC-Page-ID:page_id:uint64`. The name
+    /// defaults to the header in snake case and the type to `utf8`. May be
+    /// given more than once.
+    #[clap(long, value_parser = parse_header_column)]
+    header_column: Vec<HeaderColumn>,
+
+    /// Store record blocks as `LargeBinary`, which is needed once a row group's
+    /// blocks total more than 2 GiB.
+    #[clap(long, conflicts_with_all = ["max_body_bytes", "spill_bodies"])]
+    large_binary: bool,
+
+    /// Truncate record blocks to this many bytes, flagging truncated blocks in
+    /// a `body_truncated` column.
+    #[clap(long, value_parser, conflicts_with = "spill_bodies")]
+    max_body_bytes: Option<usize>,
+
+    /// Write record blocks larger than `--spill-threshold` to a file in this
+    /// directory rather than to the Parquet, locating each in a `body_ref`
+    /// column.
+    #[clap(long, value_parser)]
+    spill_bodies: Option<PathBuf>,
+
+    /// The size in bytes past which record blocks are written to the
+    /// `--spill-bodies` directory.
+    #[clap(long, value_parser, default_value = "16777216")]
+    spill_threshold: usize,
+
+    /// Store `content_length` and `segment_total_length` as 32-bit integers,
+    /// as earlier versions did, rather than 64-bit ones.
+    #[clap(long)]
+    legacy_lengths: bool,
+
+    /// The precision with which dates are stored, overriding that of the
+    /// schema.
+    #[clap(long, value_enum, value_parser)]
+    time_unit: Option<OptTimeUnit>,
+
+    /// How to handle malformed WARC records.
+    #[clap(long, value_enum, value_parser, default_value_t = OptErrorPolicy::Fail)]
+    on_error: OptErrorPolicy,
+
+    /// Write malformed WARC records to this path instead of failing, in place
+    /// of any other `--on-error` policy. Records are written by one job on one
+    /// thread, so `--jobs` may not exceed 1 and `--threads` may not be given.
+    #[clap(long, value_parser, conflicts_with = "threads")]
+    quarantine: Option<PathBuf>,
+
+    /// Read each gzipped input on this many threads, decompressing and
+    /// converting chunks of it at once. STDIN is read on a single thread.
+    #[clap(long, value_parser, conflicts_with = "output_dir")]
+    threads: Option<usize>,
+
+    /// Write Parquet to this path rather than to STDOUT. The file is written
+    /// under a temporary name and renamed into place once complete, so that a
+    /// failed conversion leaves nothing behind.
+    #[clap(short, long, value_parser, conflicts_with = "output_dir")]
+    output: Option<PathBuf>,
+
+    /// Start a new numbered output file, e.g. `out-00002.parquet` for
+    /// `--output out.parquet`, once the current one holds this many rows.
+    #[clap(long, value_parser, requires = "output")]
+    max_file_rows: Option<usize>,
+
+    /// Start a new numbered output file once the current one reaches about
+    /// this many bytes. Files are only split between row groups.
+    #[clap(long, value_parser, requires = "output")]
+    max_file_bytes: Option<usize>,
+
+    /// Write Parquet to files in this directory rather than to STDOUT, one per
+    /// input named after it unless `--merge` is given.
+    #[clap(long, value_parser)]
+    output_dir: Option<PathBuf>,
+
+    /// The number of inputs converted at once with `--output-dir`.
+    #[clap(long, value_parser, default_value = "1", requires = "output_dir")]
+    jobs: usize,
+
+    /// Merge the inputs into one `part-NNNNN.parquet` file per job in
+    /// `--output-dir`.
+    #[clap(long, requires = "output_dir")]
+    merge: bool,
+
+    /// How inputs are handed out to jobs with `--merge`: `input` keeps each
+    /// output file to a fixed run of the inputs in the order given, while
+    /// `completion` hands each input to the first free job.
+    #[clap(long, value_enum, value_parser, default_value_t = OptOrder::Input)]
+    order: OptOrder,
+
+    /// Write a Hive-style partitioned dataset into `--output-dir`, with one
+    /// directory level per key, e.g. `type=response/crawl_date=2020-07-08/`.
+    /// Keys are columns of the output, or `crawl_date` for the day of the
+    /// record's date or `host` for the host of its target URI. Inputs are
+    /// merged as with `--merge`.
+    #[clap(long, value_parser, value_delimiter = ',', requires = "output_dir")]
+    partition_by: Vec<PartitionKey>,
+
+    /// Write a CDX index of the WARC inputs rather than Parquet of their
+    /// records: `cdxj` or `cdx` lines as `pywb`'s `cdx-indexer` writes them,
+    /// or `parquet` with a column per CDXJ field. Entries are sorted by SURT
+    /// and timestamp; past 256 MiB of entries, sorted runs are spilled to
+    /// files beside the output, or in the temporary directory, and merged.
+    #[clap(
+        long,
+        value_enum,
+        value_parser,
+        conflicts_with_all = ["output_dir", "max_file_rows", "max_file_bytes"]
+    )]
+    index: Option<OptIndex>,
+
+    /// Index records of every type with `--index`, rather than only
+    /// `response`, `revisit` and `resource` records.
+    #[clap(long, requires = "index")]
+    index_all_records: bool,
 }
 
-fn write_row_groups<W: Write + Send, R: BufRead>(
-    writer: &mut ArrowWriter<W>,
-    reader: &mut WarcToArrowReader<R>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    for record_batch in reader.iter_reader() {
-        writer.write(&record_batch?)?;
+fn error_policy(args: &Args, append: bool) -> io::Result<ErrorPolicy> {
+    if let Some(quarantine) = &args.quarantine {
+        let file = if append {
+            OpenOptions::new().append(true).open(quarantine)?
+        } else {
+            File::create(quarantine)?
+        };
+        let sink = BufWriter::new(file);
+        return Ok(ErrorPolicy::Quarantine(Box::new(sink)));
     }
-    Ok(())
+
+    Ok(match args.on_error {
+        OptErrorPolicy::Fail => ErrorPolicy::Fail,
+        OptErrorPolicy::Skip => ErrorPolicy::Skip,
+        OptErrorPolicy::NullOut => ErrorPolicy::NullOut,
+    })
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+fn body_policy(args: &Args) -> BodyPolicy {
+    if let Some(directory) = &args.spill_bodies {
+        return BodyPolicy::External {
+            directory: directory.clone(),
+            threshold: args.spill_threshold,
+        };
+    }
+
+    match args.max_body_bytes {
+        Some(max_body_bytes) => BodyPolicy::MaxBodyBytes(max_body_bytes),
+        None if args.large_binary => BodyPolicy::LargeBinary,
+        None => BodyPolicy::Binary,
+    }
+}
+
+/// Returns the name of a WARC input, or `None` for STDIN.
+fn input_name(warc_input: &Path) -> Option<String> {
+    (warc_input.as_os_str() != STDIN_MARKER).then(|| warc_input.to_string_lossy().into_owned())
+}
+
+/// Configures a reader over one WARC input. Inputs after the first append to
+/// the quarantine file rather than replacing it.
+fn configure_reader<R: BufRead>(
+    args: &Args,
+    schema: &SchemaRef,
+    reader_builder: WarcToArrowReaderBuilder<R>,
+    append: bool,
+) -> Result<WarcToArrowReaderBuilder<R>, WarcParquetError> {
+    let mut reader_builder = reader_builder
+        .with_schema(schema.clone())
+        .with_batch_size(args.batch_size)
+        .with_max_decoded_payload_bytes(args.max_decoded_payload_bytes)
+        .with_digest_algorithm(args.digest_algorithm.clone().into())
+        .with_digest_encoding(args.digest_encoding.clone().into())
+        .with_legacy_lengths(args.legacy_lengths)
+        .with_error_policy(error_policy(args, append)?)
+        .with_body_policy(body_policy(args));
+    for header_column in args.header_column.clone() {
+        reader_builder = reader_builder.with_header_column(
+            header_column.header,
+            header_column.name,
+            header_column.data_type,
+        );
+    }
+    let input_compression = if args.gzipped {
+        Some(WarcCompression::Gzip)
+    } else {
+        args.input_compression.clone().into()
+    };
+    if let Some(input_compression) = input_compression {
+        reader_builder = reader_builder.with_compression(input_compression);
+    }
+    if let Some(max_batch_bytes) = args.max_batch_bytes {
+        reader_builder = reader_builder.with_max_batch_bytes(max_batch_bytes);
+    }
+    if let Some(time_unit) = args.time_unit.clone() {
+        reader_builder = reader_builder.with_time_unit(time_unit.into());
+    }
+
+    Ok(reader_builder)
+}
+
+/// Where Parquet is written when not written to `--output-dir`.
+enum Output {
+    Stdout(io::Stdout),
+    File(AtomicFile),
+}
+
+impl Output {
+    /// Finishes writing, renaming an output file into place.
+    fn commit(self) -> io::Result<()> {
+        match self {
+            Output::Stdout(mut stdout) => stdout.flush(),
+            Output::File(file) => file.commit(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Stdout(stdout) => stdout.write(buf),
+            Output::File(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stdout(stdout) => stdout.flush(),
+            Output::File(file) => file.flush(),
+        }
+    }
+}
+
+/// Sorts the CDX index entries of the record batches written to it.
+struct IndexSink {
+    indexer: CdxIndexer,
+    sorter: CdxSorter,
+}
+
+impl ParquetSink for IndexSink {
+    fn write(&mut self, record_batch: &RecordBatch) -> Result<(), WarcParquetError> {
+        self.sorter.push(self.indexer.index(record_batch)?)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), WarcParquetError> {
+        Ok(())
+    }
+}
+
+/// A reader over one WARC input, read on one thread or several.
+enum InputReader {
+    Sequential(Box<WarcToArrowReader<Box<dyn BufRead>>>),
+    Parallel(ParallelReader),
+}
+
+impl InputReader {
+    fn schema(&self) -> SchemaRef {
+        match self {
+            InputReader::Sequential(reader) => reader.schema(),
+            InputReader::Parallel(reader) => reader.schema(),
+        }
+    }
+
+    /// Writes the input's record batches to the writer.
+    fn write_to<S: ParquetSink>(
+        &mut self,
+        writer: &mut S,
+        options: &ConvertOptions,
+    ) -> Result<ConvertSummary, WarcParquetError> {
+        match self {
+            InputReader::Sequential(reader) => {
+                let mut iter_reader = reader.iter_reader();
+                write_parquet(writer, &mut iter_reader, options)?;
+                Ok(ConvertSummary {
+                    records_skipped: iter_reader.records_skipped(),
+                    records_quarantined: iter_reader.records_quarantined(),
+                    fields_nulled: iter_reader.fields_nulled(),
+                    digest_mismatches: iter_reader.digest_mismatches(),
+                    ..Default::default()
+                })
+            }
+            InputReader::Parallel(reader) => {
+                write_parquet(writer, &mut *reader, options)?;
+                Ok(ConvertSummary {
+                    records_skipped: reader.records_skipped(),
+                    records_quarantined: reader.records_quarantined(),
+                    fields_nulled: reader.fields_nulled(),
+                    digest_mismatches: reader.digest_mismatches(),
+                    ..Default::default()
+                })
+            }
+        }
+    }
+}
 
-    let schema = WARC_1_0_SCHEMA.clone();
+/// Builds a reader over one WARC input, which may be STDIN.
+fn build_reader(
+    args: &Arc<Args>,
+    schema: &SchemaRef,
+    warc_input: &Path,
+    append: bool,
+) -> Result<InputReader, Box<dyn std::error::Error>> {
+    if let (Some(threads), Some(_)) = (args.threads, input_name(warc_input)) {
+        let (args, schema) = (args.clone(), schema.clone());
+        let reader = ParallelReader::open(warc_input, threads, move |reader_builder| {
+            configure_reader(&args, &schema, reader_builder, append)
+        })?;
+        return Ok(InputReader::Parallel(reader));
+    }
 
-    let stream: Box<dyn BufRead> = if args.warc_input == PathBuf::from(STDIN_MARKER) {
+    let stream: Box<dyn BufRead> = if warc_input.as_os_str() == STDIN_MARKER {
         Box::new(BufReader::with_capacity(MB, io::stdin()))
     } else {
         Box::new(BufReader::with_capacity(
             MB,
-            OpenOptions::new().read(true).open(args.warc_input)?,
+            OpenOptions::new().read(true).open(warc_input)?,
         ))
     };
 
+    let mut reader_builder = WarcToArrowReader::builder(stream);
+    if let Some(source_filename) = input_name(warc_input) {
+        reader_builder = reader_builder.with_source_filename(source_filename);
+    }
+
+    let reader = configure_reader(args, schema, reader_builder, append)?.build()?;
+    Ok(InputReader::Sequential(Box::new(reader)))
+}
+
+/// Writes each input in turn to the sink, opening each only once it is
+/// reached.
+fn write_inputs<S: ParquetSink>(
+    sink: &mut S,
+    mut reader: InputReader,
+    mut readers: impl Iterator<Item = Result<InputReader, Box<dyn std::error::Error>>>,
+    convert_options: &ConvertOptions,
+) -> Result<ConvertSummary, Box<dyn std::error::Error>> {
+    let mut summary = ConvertSummary::default();
+    loop {
+        let input_summary = reader.write_to(sink, convert_options)?;
+        summary.records_skipped += input_summary.records_skipped;
+        summary.records_quarantined += input_summary.records_quarantined;
+        summary.fields_nulled += input_summary.fields_nulled;
+        summary.digest_mismatches += input_summary.digest_mismatches;
+
+        match readers.next() {
+            Some(next_reader) => reader = next_reader?,
+            None => break,
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Reports the malformed input a conversion tolerated, and any records whose
+/// digests do not match.
+fn report(summary: &ConvertSummary) {
+    if summary.records_skipped > 0 {
+        eprintln!("Skipped {} malformed records.", summary.records_skipped);
+    }
+    if summary.records_quarantined > 0 {
+        eprintln!(
+            "Quarantined {} malformed records.",
+            summary.records_quarantined
+        );
+    }
+    if summary.fields_nulled > 0 {
+        eprintln!("Nulled {} malformed fields.", summary.fields_nulled);
+    }
+    if summary.digest_mismatches > 0 {
+        eprintln!(
+            "Found {} records with mismatched digests.",
+            summary.digest_mismatches
+        );
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Arc::new(Args::parse());
+
+    if args.quarantine.is_some() {
+        if !matches!(args.on_error, OptErrorPolicy::Fail) {
+            return Err("--quarantine cannot be combined with --on-error skip or null-out".into());
+        }
+        // Jobs appending to the one file would interleave their records.
+        if args.jobs > 1 {
+            return Err("--quarantine cannot be combined with more than one job".into());
+        }
+    }
+
+    let mut extra_columns = Vec::new();
+    if args.headers {
+        extra_columns.push(WarcColumn::Headers);
+    }
+    if args.http {
+        extra_columns.extend_from_slice(WarcColumn::HTTP);
+    }
+    if args.url {
+        extra_columns.extend_from_slice(WarcColumn::URL);
+    }
+    if args.decode_payload {
+        extra_columns.extend([WarcColumn::DecodedPayload, WarcColumn::PayloadDecodeFailed]);
+    }
+    if args.verify_digests {
+        extra_columns.extend([WarcColumn::BlockDigestValid, WarcColumn::PayloadDigestValid]);
+    }
+    if args.compute_digests {
+        extra_columns.extend([
+            WarcColumn::ComputedBlockDigest,
+            WarcColumn::ComputedPayloadDigest,
+        ]);
+    }
+    if args.offsets {
+        extra_columns.extend([
+            WarcColumn::WarcOffset,
+            WarcColumn::WarcLength,
+            WarcColumn::WarcFilename,
+        ]);
+    }
+    // Inputs written to the same output are told apart by their filename.
+    let shared_output = args.output_dir.is_none() || args.merge || !args.partition_by.is_empty();
+    if args.source_file || (args.warc_input.len() > 1 && shared_output) {
+        extra_columns.push(WarcColumn::SourceFile);
+    }
+    if args.max_body_bytes.is_some() {
+        extra_columns.push(WarcColumn::BodyTruncated);
+    }
+    if args.spill_bodies.is_some() {
+        extra_columns.push(WarcColumn::BodyRef);
+    }
+
+    let mut schema: SchemaRef = args.schema.clone().into();
+    if args.index.is_some() {
+        schema = WarcColumn::schema(CdxIndexer::COLUMNS);
+    } else if !extra_columns.is_empty() {
+        let fields = schema
+            .fields()
+            .iter()
+            .cloned()
+            .chain(extra_columns.iter().map(|column| Arc::new(column.field())));
+        schema = Arc::new(Schema::new(fields.collect::<Vec<_>>()));
+    }
+
     let writer_props = WriterProperties::builder()
         .set_created_by(String::from("warc-parquet"))
-        .set_compression(args.compression.into())
+        .set_compression(args.compression.clone().into())
         .set_max_row_group_size(args.max_row_group_size)
         .build();
-    let mut writer = ArrowWriter::try_new(io::stdout(), schema.clone(), Some(writer_props))?;
-
-    let batch_size = args.batch_size;
-    if args.gzipped {
-        let gzip_stream = BufReader::new(GzipReader::new(stream)?);
-        let mut reader = WarcToArrowReader::builder(gzip_stream)
-            .with_schema(schema)
-            .with_batch_size(batch_size)
-            .build();
-        write_row_groups(&mut writer, &mut reader)?;
-    } else {
-        let mut reader = WarcToArrowReader::builder(stream)
-            .with_schema(schema)
-            .with_batch_size(batch_size)
-            .build();
-        write_row_groups(&mut writer, &mut reader)?;
+    let convert_options = ConvertOptions {
+        jobs: args.jobs,
+        order: args.order.clone().into(),
+        writer_properties: writer_props.clone(),
+        flush_batches: args.max_batch_bytes.is_some(),
+    };
+
+    if let Some(output_dir) = &args.output_dir {
+        if args
+            .warc_input
+            .iter()
+            .any(|warc_input| input_name(warc_input).is_none())
+        {
+            return Err("STDIN cannot be converted with --output-dir".into());
+        }
+
+        let output = if !args.partition_by.is_empty() {
+            ConvertOutput::Partitioned {
+                directory: output_dir.clone(),
+                keys: args.partition_by.clone(),
+            }
+        } else if args.merge {
+            ConvertOutput::Merged(output_dir.clone())
+        } else {
+            ConvertOutput::PerInput(output_dir.clone())
+        };
+        // Each input appends to the quarantine file, so it is emptied up front.
+        if let Some(quarantine) = &args.quarantine {
+            File::create(quarantine)?;
+        }
+        let summary = convert_many(&args.warc_input, &output, &convert_options, |builder| {
+            configure_reader(&args, &schema, builder, true)
+        })?;
+        report(&summary);
+
+        return Ok(());
     }
 
-    writer.close()?;
+    let mut readers = args
+        .warc_input
+        .iter()
+        .enumerate()
+        .map(|(index, warc_input)| build_reader(&args, &schema, warc_input, index > 0));
+    let Some(reader) = readers.next().transpose()? else {
+        return Ok(());
+    };
+
+    if let Some(index) = &args.index {
+        // Runs are spilled beside the output, where there is presumably room
+        // for the index itself.
+        let spill_directory = match args.output.as_deref().and_then(Path::parent) {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            Some(_) => PathBuf::from("."),
+            None => std::env::temp_dir(),
+        };
+        let mut sink = IndexSink {
+            indexer: CdxIndexer::new().with_all_records(args.index_all_records),
+            sorter: CdxSorter::new(spill_directory),
+        };
+        let summary = write_inputs(&mut sink, reader, readers, &convert_options)?;
+        let index_batches = sink.sorter.finish()?;
+
+        let output = match &args.output {
+            Some(output) => Output::File(AtomicFile::create(output)?),
+            None => Output::Stdout(io::stdout()),
+        };
+        let output = match index {
+            OptIndex::Parquet => {
+                let mut writer =
+                    ArrowWriter::try_new(output, CDX_SCHEMA.clone(), Some(writer_props))?;
+                for index_batch in index_batches {
+                    writer.write(&index_batch?)?;
+                }
+                writer.into_inner()?
+            }
+            OptIndex::Cdxj | OptIndex::Cdx => {
+                let format = match index {
+                    OptIndex::Cdx => CdxFormat::Cdx,
+                    _ => CdxFormat::Cdxj,
+                };
+                let mut writer = BufWriter::new(output);
+                format.write_header(&mut writer)?;
+                for index_batch in index_batches {
+                    format.write_lines(&mut writer, &index_batch?)?;
+                }
+                writer.into_inner().map_err(|err| err.into_error())?
+            }
+        };
+        output.commit()?;
+        report(&summary);
+
+        return Ok(());
+    }
+
+    let output_schema = reader.schema();
+    let summary = match &args.output {
+        Some(output) if args.max_file_rows.is_some() || args.max_file_bytes.is_some() => {
+            let mut writer = RollingParquetWriter::new(output, output_schema, Some(writer_props));
+            if let Some(max_file_rows) = args.max_file_rows {
+                writer = writer.with_max_file_rows(max_file_rows);
+            }
+            if let Some(max_file_bytes) = args.max_file_bytes {
+                writer = writer.with_max_file_bytes(max_file_bytes);
+            }
+            let summary = write_inputs(&mut writer, reader, readers, &convert_options)?;
+            writer.close()?;
+            summary
+        }
+        output => {
+            let output = match output {
+                Some(output) => Output::File(AtomicFile::create(output)?),
+                None => Output::Stdout(io::stdout()),
+            };
+            let mut writer = ArrowWriter::try_new(output, output_schema, Some(writer_props))?;
+            let summary = write_inputs(&mut writer, reader, readers, &convert_options)?;
+            // Should any input fail, the output file is dropped before this
+            // point and so removed.
+            writer.into_inner()?.commit()?;
+            summary
+        }
+    };
+    report(&summary);
 
     Ok(())
 }
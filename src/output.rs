@@ -0,0 +1,325 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+use arrow::{datatypes::SchemaRef, record_batch::RecordBatch};
+use parquet::{arrow::ArrowWriter, file::properties::WriterProperties};
+
+use crate::error::WarcParquetError;
+
+type WriteResult<T> = Result<T, WarcParquetError>;
+
+/// A destination for record batches written as Parquet.
+pub trait ParquetSink {
+    /// Writes the record batch, buffering it into the current row group.
+    fn write(&mut self, record_batch: &RecordBatch) -> WriteResult<()>;
+
+    /// Closes the current row group.
+    fn flush(&mut self) -> WriteResult<()>;
+}
+
+impl<W: Write + Send> ParquetSink for ArrowWriter<W> {
+    fn write(&mut self, record_batch: &RecordBatch) -> WriteResult<()> {
+        Ok(ArrowWriter::write(self, record_batch)?)
+    }
+
+    fn flush(&mut self) -> WriteResult<()> {
+        Ok(ArrowWriter::flush(self)?)
+    }
+}
+
+/// A file written under a temporary name beside its destination and renamed
+/// into place once complete, so that a conversion which fails or crashes
+/// part way never leaves a truncated file at the destination.
+///
+/// The temporary file is removed if the `AtomicFile` is dropped without being
+/// [committed](AtomicFile::commit). A process which is killed outright leaves
+/// it behind, however its name begins with a dot and ends in `.tmp`, so it is
+/// not mistaken for the finished file.
+///
+/// # Example
+///
+/// ```rust
+/// use std::io::Write;
+///
+/// use tempfile::tempdir;
+/// use warc_parquet::AtomicFile;
+///
+/// # fn main() {
+/// let directory = tempdir().unwrap();
+/// let path = directory.path().join("example.parquet");
+///
+/// let mut file = AtomicFile::create(&path).unwrap();
+/// file.write_all(b"PAR1").unwrap();
+/// assert!(!path.exists());
+///
+/// file.commit().unwrap();
+/// assert!(path.exists());
+/// # }
+/// ```
+pub struct AtomicFile {
+    file: File,
+    temp_path: PathBuf,
+    path: PathBuf,
+    committed: bool,
+}
+
+impl AtomicFile {
+    /// Creates a temporary file in the directory of `path`, to be renamed to
+    /// `path` once committed. The temporary file is given a name no other file
+    /// holds, so that concurrent writers do not clobber one another.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file_name = path.file_name().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "output path has no file name")
+        })?;
+        let directory = path.parent().unwrap_or(Path::new(""));
+
+        let mut attempt = 0;
+        loop {
+            let temp_name = format!(
+                ".{}.{}-{attempt}.tmp",
+                file_name.to_string_lossy(),
+                process::id()
+            );
+            let temp_path = directory.join(temp_name);
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&temp_path)
+            {
+                Ok(file) => {
+                    return Ok(Self {
+                        file,
+                        temp_path,
+                        path,
+                        committed: false,
+                    })
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => attempt += 1,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Returns the path the file is renamed to once committed.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Flushes the file to disk and renames it into place, replacing any file
+    /// already there.
+    pub fn commit(mut self) -> io::Result<()> {
+        self.file.sync_all()?;
+        fs::rename(&self.temp_path, &self.path)?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            // There is nothing more to be done should the removal fail.
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+/// Writes Parquet to a series of numbered files, starting a new file whenever
+/// the current one reaches a number of rows or bytes.
+///
+/// Files are named after the given path with a number inserted before its
+/// extension, so that `out.parquet` becomes `out-00001.parquet`,
+/// `out-00002.parquet` and so on. A file is only ever ended between row
+/// groups: when a row limit falls within a record batch, the batch is split
+/// and the row group closed early. The byte limit is approximate, being
+/// checked against the compressed size of a file's row groups plus an
+/// estimate of the one in progress after each batch.
+///
+/// Each file is written as an [`AtomicFile`], so files appear once complete.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use arrow::{
+///     array::UInt64Array,
+///     datatypes::{DataType, Field, Schema},
+///     record_batch::RecordBatch,
+/// };
+/// use tempfile::tempdir;
+/// use warc_parquet::{ParquetSink, RollingParquetWriter};
+///
+/// # fn main() {
+/// let schema = Arc::new(Schema::new(vec![Field::new("n", DataType::UInt64, false)]));
+/// let record_batch = RecordBatch::try_new(
+///     schema.clone(),
+///     vec![Arc::new(UInt64Array::from_iter_values(0..5))],
+/// )
+/// .unwrap();
+///
+/// let directory = tempdir().unwrap();
+/// let mut writer = RollingParquetWriter::new(directory.path().join("out.parquet"), schema, None)
+///     .with_max_file_rows(2);
+/// writer.write(&record_batch).unwrap();
+/// let paths = writer.close().unwrap();
+///
+/// assert_eq!(paths.len(), 3);
+/// assert_eq!(paths[0], directory.path().join("out-00001.parquet"));
+/// # }
+/// ```
+pub struct RollingParquetWriter {
+    path: PathBuf,
+    schema: SchemaRef,
+    properties: Option<WriterProperties>,
+    max_file_rows: Option<usize>,
+    max_file_bytes: Option<usize>,
+    writer: Option<ArrowWriter<AtomicFile>>,
+    file_rows: usize,
+    paths: Vec<PathBuf>,
+}
+
+impl RollingParquetWriter {
+    /// Creates a writer of files named after `path`. No file is created until
+    /// the first record batch is written.
+    pub fn new(
+        path: impl Into<PathBuf>,
+        schema: SchemaRef,
+        properties: Option<WriterProperties>,
+    ) -> Self {
+        Self {
+            path: path.into(),
+            schema,
+            properties,
+            max_file_rows: None,
+            max_file_bytes: None,
+            writer: None,
+            file_rows: 0,
+            paths: Vec::new(),
+        }
+    }
+
+    /// Sets the most rows a file may hold.
+    pub fn with_max_file_rows(mut self, max_file_rows: usize) -> Self {
+        self.max_file_rows = Some(max_file_rows.max(1));
+        self
+    }
+
+    /// Sets the size in bytes past which a new file is started.
+    pub fn with_max_file_bytes(mut self, max_file_bytes: usize) -> Self {
+        self.max_file_bytes = Some(max_file_bytes);
+        self
+    }
+
+    /// Completes the current file, returning the paths of every file written.
+    /// A single empty file is written if no rows were.
+    pub fn close(mut self) -> WriteResult<Vec<PathBuf>> {
+        if self.paths.is_empty() {
+            self.writer()?;
+        }
+        self.roll()?;
+        Ok(self.paths)
+    }
+
+    /// Returns the writer of the current file, starting one if need be.
+    fn writer(&mut self) -> WriteResult<&mut ArrowWriter<AtomicFile>> {
+        match &mut self.writer {
+            Some(writer) => Ok(writer),
+            writer => {
+                let path = numbered_path(&self.path, self.paths.len() + 1);
+                let file_writer = ArrowWriter::try_new(
+                    AtomicFile::create(path)?,
+                    self.schema.clone(),
+                    self.properties.clone(),
+                )?;
+                Ok(writer.insert(file_writer))
+            }
+        }
+    }
+
+    /// Completes the current file, if any.
+    fn roll(&mut self) -> WriteResult<()> {
+        if let Some(writer) = self.writer.take() {
+            let file = writer.into_inner()?;
+            let path = file.path().to_path_buf();
+            file.commit()?;
+            self.paths.push(path);
+            self.file_rows = 0;
+        }
+        Ok(())
+    }
+
+    fn is_full(&self) -> bool {
+        let Some(writer) = &self.writer else {
+            return false;
+        };
+
+        let rows_full = self
+            .max_file_rows
+            .is_some_and(|max_file_rows| self.file_rows >= max_file_rows);
+        let bytes_full = self.max_file_bytes.is_some_and(|max_file_bytes| {
+            let flushed_bytes: i64 = writer
+                .flushed_row_groups()
+                .iter()
+                .map(|row_group| row_group.compressed_size())
+                .sum();
+            flushed_bytes as usize + writer.in_progress_size() >= max_file_bytes
+        });
+        rows_full || bytes_full
+    }
+}
+
+impl ParquetSink for RollingParquetWriter {
+    fn write(&mut self, record_batch: &RecordBatch) -> WriteResult<()> {
+        let mut offset = 0;
+        while offset < record_batch.num_rows() {
+            let rows = match self.max_file_rows {
+                Some(max_file_rows) => max_file_rows - self.file_rows,
+                None => record_batch.num_rows(),
+            }
+            .min(record_batch.num_rows() - offset);
+
+            self.writer()?.write(&record_batch.slice(offset, rows))?;
+            self.file_rows += rows;
+            offset += rows;
+
+            if self.is_full() {
+                self.roll()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> WriteResult<()> {
+        match &mut self.writer {
+            Some(writer) => Ok(writer.flush()?),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Returns the path with the file number inserted before its extension.
+pub(crate) fn numbered_path(path: &Path, number: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{stem}-{number:05}.{}", extension.to_string_lossy()),
+        None => format!("{stem}-{number:05}"),
+    };
+    path.with_file_name(file_name)
+}
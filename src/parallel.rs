@@ -0,0 +1,586 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use arrow::{datatypes::SchemaRef, record_batch::RecordBatch};
+use libflate::gzip;
+
+use crate::{
+    error::WarcParquetError,
+    reader::{WarcToArrowReader, WarcToArrowReaderBuilder},
+    source::WarcCompression,
+};
+
+type ReaderResult<T> = Result<T, WarcParquetError>;
+
+/// A builder of a reader over one chunk of a file.
+type ChunkBuilder = WarcToArrowReaderBuilder<BufReader<File>>;
+
+type Configure = dyn Fn(ChunkBuilder) -> ReaderResult<ChunkBuilder> + Send + Sync;
+
+/// The bounds on the size of the chunks a file is split into. Chunks are sized
+/// so that each thread has several to read, which evens out the work when
+/// chunks decompress at different rates.
+const MIN_CHUNK_BYTES: u64 = 4 * 1024 * 1024;
+const MAX_CHUNK_BYTES: u64 = 64 * 1024 * 1024;
+
+/// The number of bytes scanned for gzip members at a time.
+const SCAN_BLOCK_BYTES: u64 = 1024 * 1024;
+
+/// The number of record batches a chunk may read ahead of the consumer.
+const CHUNK_BACKLOG: usize = 2;
+
+/// The magic number and compression method beginning each gzip member.
+const GZIP_MEMBER_MAGIC: [u8; 3] = [0x1f, 0x8b, 0x08];
+
+/// Reads a gzipped WARC file on several threads at once.
+///
+/// Each record of a `.warc.gz` file is conventionally compressed as a gzip
+/// member of its own, so the file may be split into chunks which are
+/// decompressed and translated to Arrow independently. Each chunk reads the
+/// records whose members begin within it, starting from the first member found
+/// within it. Gzip offers no way of telling a member apart from compressed
+/// bytes which happen to resemble one, so each chunk is checked against where
+/// the chunk before it stopped, and read again from there if it guessed
+/// wrong.
+///
+/// Record batches are returned in the order of the file, as they would be by
+/// [`WarcToArrowReader`], however no batch spans two chunks. Files with any
+/// other compression are read on a single thread.
+///
+/// # Example
+///
+/// ```rust
+/// use std::io::Write;
+///
+/// use libflate::gzip::Encoder;
+/// use tempfile::NamedTempFile;
+/// use warc_parquet::{ParallelReader, WarcColumn};
+///
+/// # fn main() {
+/// let mut file = NamedTempFile::new().unwrap();
+/// for index in 0..3 {
+///     let mut encoder = Encoder::new(Vec::new()).unwrap();
+///     write!(
+///         encoder,
+///         "WARC/1.0\r\n\
+///          WARC-Type: resource\r\n\
+///          Content-Length: 0\r\n\
+///          WARC-Record-Id: <urn:test:parallel:record-{index}>\r\n\
+///          WARC-Date: 2020-07-08T02:52:55Z\r\n\
+///          \r\n\
+///          \r\n\
+///          \r\n"
+///     )
+///     .unwrap();
+///     file.write_all(&encoder.finish().into_result().unwrap()).unwrap();
+/// }
+///
+/// let reader = ParallelReader::open(file.path(), 4, |builder| {
+///     Ok(builder.with_columns(&[WarcColumn::Id, WarcColumn::WarcOffset]))
+/// })
+/// .unwrap();
+/// let rows: usize = reader.map(|record_batch| record_batch.unwrap().num_rows()).sum();
+/// assert_eq!(rows, 3);
+/// # }
+/// ```
+pub struct ParallelReader {
+    shared: Arc<Shared>,
+    schema: SchemaRef,
+    /// The receiving ends of the chunks not yet reached.
+    receivers: Vec<Option<Receiver<ChunkMessage>>>,
+    next_chunk: usize,
+    /// The receiving end of the chunk being read.
+    current: Option<Receiver<ChunkMessage>>,
+    /// The offset in the file at which the next chunk must begin, being where
+    /// the one before it stopped.
+    source_offset: u64,
+    /// The number of bytes of WARC read before the current chunk.
+    position: u64,
+    ended: bool,
+    records_skipped: u64,
+    records_quarantined: u64,
+    fields_nulled: u64,
+    digest_mismatches: u64,
+}
+
+impl ParallelReader {
+    /// Opens the WARC file at the path for reading on up to `threads`
+    /// threads.
+    ///
+    /// A reader is built for each chunk from `configure`, which is handed a
+    /// builder over the file with its
+    /// [source filename](WarcToArrowReaderBuilder::with_source_filename)
+    /// already set. It is called from the reading threads, once per chunk, so
+    /// a quarantine sink it provides receives the malformed records of that
+    /// chunk alone.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file cannot be opened, or if `configure` or building the
+    /// reader of the first chunk fails.
+    ///
+    /// # Example
+    ///
+    /// A chunk may begin partway through a record whose block holds what looks
+    /// like a gzip member of a record of its own, here stored verbatim within a
+    /// member written without compression. The chunk's records are then read
+    /// again from where the chunk before it stopped, so that the result is the
+    /// same as reading the file on a single thread.
+    ///
+    /// ```rust
+    /// use std::io::{BufReader, Write};
+    ///
+    /// use arrow::record_batch::RecordBatch;
+    /// use libflate::gzip::{EncodeOptions, Encoder};
+    /// use tempfile::NamedTempFile;
+    /// use warc_parquet::{ParallelReader, WarcColumn, WarcToArrowReader};
+    ///
+    /// # fn main() {
+    /// let record = |id: &str, block: &[u8]| {
+    ///     let header_block = format!(
+    ///         "WARC/1.0\r\n\
+    ///          WARC-Type: resource\r\n\
+    ///          Content-Length: {}\r\n\
+    ///          WARC-Record-Id: <urn:test:misguess:{id}>\r\n\
+    ///          WARC-Date: 2020-07-08T02:52:55Z\r\n\
+    ///          \r\n",
+    ///         block.len()
+    ///     );
+    ///     [header_block.as_bytes(), block, b"\r\n\r\n"].concat()
+    /// };
+    /// let gzip = |record: &[u8], options| {
+    ///     let mut encoder = Encoder::with_options(Vec::new(), options).unwrap();
+    ///     encoder.write_all(record).unwrap();
+    ///     encoder.finish().into_result().unwrap()
+    /// };
+    ///
+    /// // Chunks are at least 4 MiB, so the fake member lands in the second.
+    /// let fake_member = gzip(&record("fake", b""), EncodeOptions::new());
+    /// let block = [vec![b'a'; 4 * 1024 * 1024 + 1024], fake_member].concat();
+    ///
+    /// let mut file = NamedTempFile::new().unwrap();
+    /// let stored = EncodeOptions::new().no_compression();
+    /// file.write_all(&gzip(&record("record-0", &block), stored)).unwrap();
+    /// for id in ["record-1", "record-2"] {
+    ///     file.write_all(&gzip(&record(id, b"Hi"), EncodeOptions::new())).unwrap();
+    /// }
+    ///
+    /// let columns = [WarcColumn::Id, WarcColumn::WarcOffset, WarcColumn::WarcLength];
+    /// let reader = ParallelReader::open(file.path(), 4, move |builder| {
+    ///     Ok(builder.with_columns(&columns))
+    /// })
+    /// .unwrap();
+    /// let schema = reader.schema();
+    /// let record_batches: Vec<_> = reader.map(Result::unwrap).collect();
+    /// let parallel = arrow::compute::concat_batches(&schema, &record_batches).unwrap();
+    ///
+    /// let input = BufReader::new(file.reopen().unwrap());
+    /// let mut reader = WarcToArrowReader::builder(input)
+    ///     .with_columns(&columns)
+    ///     .build()
+    ///     .unwrap();
+    /// let sequential: RecordBatch = reader.iter_reader().next().unwrap().unwrap();
+    ///
+    /// assert_eq!(parallel.num_rows(), 3);
+    /// assert_eq!(parallel, sequential);
+    /// # }
+    /// ```
+    pub fn open<F>(path: impl AsRef<Path>, threads: usize, configure: F) -> ReaderResult<Self>
+    where
+        F: Fn(ChunkBuilder) -> ReaderResult<ChunkBuilder> + Send + Sync + 'static,
+    {
+        let path = path.as_ref().to_path_buf();
+        let mut file = BufReader::new(File::open(&path)?);
+        let compression = WarcCompression::detect(&mut file)?;
+        let file_len = file.get_ref().metadata()?.len();
+
+        let threads = threads.max(1);
+        let chunk_bytes = match compression {
+            WarcCompression::Gzip => {
+                (file_len / (threads as u64 * 4)).clamp(MIN_CHUNK_BYTES, MAX_CHUNK_BYTES)
+            }
+            _ => file_len.max(1),
+        };
+        let chunk_count = file_len.div_ceil(chunk_bytes).max(1) as usize;
+
+        let (senders, receivers) = (0..chunk_count)
+            .map(|_| {
+                let (sender, receiver) = mpsc::sync_channel(CHUNK_BACKLOG);
+                (Some(sender), Some(receiver))
+            })
+            .unzip();
+        let shared = Arc::new(Shared {
+            path,
+            file_len,
+            chunk_bytes,
+            chunk_count,
+            configure: Box::new(configure),
+            next_chunk: AtomicUsize::new(0),
+            cancelled: AtomicBool::new(false),
+            senders: Mutex::new(senders),
+            first_reader: Mutex::new(None),
+        });
+
+        // The first chunk's reader is built up front, so that configuration
+        // errors surface here and the schema is known.
+        let first_reader = shared.chunk_reader(0, 0)?;
+        let schema = first_reader.schema();
+        *lock(&shared.first_reader) = Some(first_reader);
+
+        for _ in 0..threads.min(chunk_count) {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name(String::from("warc-parquet-reader"))
+                .spawn(move || shared.run_worker())?;
+        }
+
+        Ok(Self {
+            shared,
+            schema,
+            receivers,
+            next_chunk: 0,
+            current: None,
+            source_offset: 0,
+            position: 0,
+            ended: false,
+            records_skipped: 0,
+            records_quarantined: 0,
+            fields_nulled: 0,
+            digest_mismatches: 0,
+        })
+    }
+
+    /// Returns the schema of the record batches produced by the reader.
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    /// Returns the number of records dropped under
+    /// [`ErrorPolicy::Skip`](crate::ErrorPolicy::Skip), as well as unusable
+    /// records dropped under
+    /// [`ErrorPolicy::NullOut`](crate::ErrorPolicy::NullOut), by the chunks
+    /// read so far.
+    pub fn records_skipped(&self) -> u64 {
+        self.records_skipped
+    }
+
+    /// Returns the number of records written to quarantine sinks under
+    /// [`ErrorPolicy::Quarantine`](crate::ErrorPolicy::Quarantine) by the
+    /// chunks read so far.
+    pub fn records_quarantined(&self) -> u64 {
+        self.records_quarantined
+    }
+
+    /// Returns the number of fields set to null under
+    /// [`ErrorPolicy::NullOut`](crate::ErrorPolicy::NullOut) by the chunks
+    /// read so far.
+    pub fn fields_nulled(&self) -> u64 {
+        self.fields_nulled
+    }
+
+    /// Returns the number of records whose block or payload digest does not
+    /// match, as reported by the
+    /// [`BlockDigestValid`](crate::WarcColumn::BlockDigestValid) and
+    /// [`PayloadDigestValid`](crate::WarcColumn::PayloadDigestValid) columns,
+    /// in the chunks read so far.
+    pub fn digest_mismatches(&self) -> u64 {
+        self.digest_mismatches
+    }
+
+    /// Returns the receiving end of the next chunk holding records, or `None`
+    /// once the file is exhausted.
+    fn next_chunk(&mut self) -> Option<Receiver<ChunkMessage>> {
+        while self.source_offset < self.shared.file_len && self.next_chunk < self.receivers.len() {
+            let index = self.next_chunk;
+            self.next_chunk += 1;
+            let receiver = self.receivers[index].take()?;
+
+            // Records beginning in this chunk were read along with the chunk
+            // before it.
+            if self.source_offset >= self.shared.chunk_end(index) {
+                continue;
+            }
+
+            if let Ok(ChunkMessage::Start(Some(source_start))) = receiver.recv() {
+                if source_start == self.source_offset {
+                    return Some(receiver);
+                }
+            }
+
+            // The chunk began elsewhere, having been misled by bytes resembling
+            // a member, so it is read again from where it ought to have begun.
+            drop(receiver);
+            let (sender, receiver) = mpsc::sync_channel(CHUNK_BACKLOG);
+            let shared = self.shared.clone();
+            let source_start = self.source_offset;
+            let spawned = thread::Builder::new()
+                .name(String::from("warc-parquet-reader"))
+                .spawn(move || shared.read_records(index, source_start, &sender));
+            return match spawned {
+                Ok(_) => Some(receiver),
+                Err(err) => {
+                    // The failure is reported in place of the chunk's records.
+                    let (sender, receiver) = mpsc::sync_channel(1);
+                    let _ = sender.send(ChunkMessage::Batch(Err(err.into())));
+                    Some(receiver)
+                }
+            };
+        }
+
+        None
+    }
+
+    /// Stops reading, releasing the threads.
+    fn finish(&mut self) {
+        self.ended = true;
+        self.shared.cancelled.store(true, Ordering::Relaxed);
+        self.current = None;
+        self.receivers.clear();
+    }
+}
+
+impl Iterator for ParallelReader {
+    type Item = ReaderResult<RecordBatch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.ended {
+            let Some(receiver) = self.current.take().or_else(|| self.next_chunk()) else {
+                self.finish();
+                break;
+            };
+
+            match receiver.recv() {
+                Ok(ChunkMessage::Batch(Ok(record_batch))) => {
+                    self.current = Some(receiver);
+                    return Some(Ok(record_batch));
+                }
+
+                Ok(ChunkMessage::Batch(Err(mut err))) => {
+                    // Offsets are counted from the start of the chunk.
+                    err.shift_offset(self.position);
+                    self.finish();
+                    return Some(Err(err));
+                }
+
+                Ok(ChunkMessage::End(chunk_end)) => {
+                    self.position += chunk_end.position;
+                    self.source_offset = chunk_end.source_offset;
+                    self.records_skipped += chunk_end.records_skipped;
+                    self.records_quarantined += chunk_end.records_quarantined;
+                    self.fields_nulled += chunk_end.fields_nulled;
+                    self.digest_mismatches += chunk_end.digest_mismatches;
+                }
+
+                Ok(ChunkMessage::Start(_)) | Err(_) => {
+                    self.finish();
+                    let err = io::Error::other("a reading thread stopped unexpectedly");
+                    return Some(Err(err.into()));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl Drop for ParallelReader {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// What a chunk sends to the consumer: where it began, then its record
+/// batches, then where it stopped.
+enum ChunkMessage {
+    /// The offset of the first member of the chunk, or `None` if no member
+    /// begins within it.
+    Start(Option<u64>),
+    Batch(ReaderResult<RecordBatch>),
+    End(ChunkEnd),
+}
+
+struct ChunkEnd {
+    /// The number of bytes of WARC the chunk read.
+    position: u64,
+    /// The offset in the file of the first record past the chunk, or the
+    /// length of the file.
+    source_offset: u64,
+    records_skipped: u64,
+    records_quarantined: u64,
+    fields_nulled: u64,
+    digest_mismatches: u64,
+}
+
+/// The state shared by the consumer and the reading threads.
+struct Shared {
+    path: PathBuf,
+    file_len: u64,
+    chunk_bytes: u64,
+    chunk_count: usize,
+    configure: Box<Configure>,
+    next_chunk: AtomicUsize,
+    cancelled: AtomicBool,
+    senders: Mutex<Vec<Option<SyncSender<ChunkMessage>>>>,
+    first_reader: Mutex<Option<WarcToArrowReader<BufReader<File>>>>,
+}
+
+impl Shared {
+    fn chunk_start(&self, index: usize) -> u64 {
+        index as u64 * self.chunk_bytes
+    }
+
+    fn chunk_end(&self, index: usize) -> u64 {
+        (self.chunk_start(index) + self.chunk_bytes).min(self.file_len)
+    }
+
+    /// Reads chunks in turn until none are left.
+    fn run_worker(&self) {
+        while !self.cancelled.load(Ordering::Relaxed) {
+            let index = self.next_chunk.fetch_add(1, Ordering::Relaxed);
+            if index >= self.chunk_count {
+                return;
+            }
+            let Some(sender) = lock(&self.senders)[index].take() else {
+                continue;
+            };
+
+            let source_start = match index {
+                0 => Ok(Some(0)),
+                _ => find_member_start(&self.path, self.chunk_start(index), self.chunk_end(index)),
+            };
+            match source_start {
+                Ok(source_start) => {
+                    // Sending fails once the consumer has no use for the chunk.
+                    if sender.send(ChunkMessage::Start(source_start)).is_ok() {
+                        if let Some(source_start) = source_start {
+                            self.read_records(index, source_start, &sender);
+                        }
+                    }
+                }
+                Err(err) => {
+                    let _ = sender.send(ChunkMessage::Batch(Err(err.into())));
+                }
+            }
+        }
+    }
+
+    /// Reads the records of the chunk, beginning with the member at
+    /// `source_start`, sending them to the consumer.
+    fn read_records(&self, index: usize, source_start: u64, sender: &SyncSender<ChunkMessage>) {
+        let mut reader = match self.chunk_reader(index, source_start) {
+            Ok(reader) => reader,
+            Err(err) => {
+                let _ = sender.send(ChunkMessage::Batch(Err(err)));
+                return;
+            }
+        };
+
+        let mut iter_reader = reader.iter_reader();
+        for record_batch in &mut iter_reader {
+            let failed = record_batch.is_err();
+            if sender.send(ChunkMessage::Batch(record_batch)).is_err() || failed {
+                return;
+            }
+        }
+        let records_skipped = iter_reader.records_skipped();
+        let records_quarantined = iter_reader.records_quarantined();
+        let fields_nulled = iter_reader.fields_nulled();
+        let digest_mismatches = iter_reader.digest_mismatches();
+
+        let Some((position, source_offset)) = reader.stream_end() else {
+            return;
+        };
+        let _ = sender.send(ChunkMessage::End(ChunkEnd {
+            position,
+            source_offset,
+            records_skipped,
+            records_quarantined,
+            fields_nulled,
+            digest_mismatches,
+        }));
+    }
+
+    /// Builds a reader over the records of the chunk, beginning with the
+    /// member at `source_start`.
+    fn chunk_reader(
+        &self,
+        index: usize,
+        source_start: u64,
+    ) -> ReaderResult<WarcToArrowReader<BufReader<File>>> {
+        if index == 0 {
+            if let Some(reader) = lock(&self.first_reader).take() {
+                return Ok(reader);
+            }
+        }
+
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(source_start))?;
+        let builder = WarcToArrowReader::builder(BufReader::new(file))
+            .with_source_filename(self.path.to_string_lossy())
+            .with_source_range(source_start, self.chunk_end(index));
+        (self.configure)(builder)?.build()
+    }
+}
+
+/// Returns the offset of the first gzip member holding a WARC record which
+/// begins between `start` and `end`, if any.
+fn find_member_start(path: &Path, start: u64, end: u64) -> io::Result<Option<u64>> {
+    let mut file = File::open(path)?;
+    let mut block = Vec::new();
+    let mut block_start = start;
+    while block_start < end {
+        file.seek(SeekFrom::Start(block_start))?;
+        block.clear();
+        (&mut file).take(SCAN_BLOCK_BYTES).read_to_end(&mut block)?;
+
+        let candidates = block
+            .windows(GZIP_MEMBER_MAGIC.len() + 1)
+            .enumerate()
+            // The reserved flag bits of a member header are never set.
+            .filter(|(_, window)| window.starts_with(&GZIP_MEMBER_MAGIC) && window[3] & 0xe0 == 0)
+            .map(|(index, _)| block_start + index as u64);
+        for candidate in candidates {
+            if candidate >= end {
+                return Ok(None);
+            }
+            if is_record_member(path, candidate)? {
+                return Ok(Some(candidate));
+            }
+        }
+
+        if (block.len() as u64) < SCAN_BLOCK_BYTES {
+            break;
+        }
+        // Blocks overlap so that a header straddling two is not missed.
+        block_start += SCAN_BLOCK_BYTES - GZIP_MEMBER_MAGIC.len() as u64;
+    }
+
+    Ok(None)
+}
+
+/// Returns whether a gzip member holding the start of a WARC record begins at
+/// the offset.
+fn is_record_member(path: &Path, offset: u64) -> io::Result<bool> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let Ok(decoder) = gzip::Decoder::new(BufReader::new(file)) else {
+        return Ok(false);
+    };
+
+    let mut version = Vec::new();
+    Ok(decoder.take(5).read_to_end(&mut version).is_ok() && version == b"WARC/")
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    // The data is left consistent even by a thread which panicked.
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
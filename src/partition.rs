@@ -0,0 +1,582 @@
+use std::{
+    collections::HashMap, convert::Infallible, fmt::Write as _, fs, path::PathBuf, str::FromStr,
+    sync::Arc,
+};
+
+use arrow::{
+    array::{Array, ArrayRef, StringArray, UInt32Array},
+    compute::{cast, take},
+    datatypes::{DataType, SchemaRef},
+    record_batch::RecordBatch,
+    util::display::array_value_to_string,
+};
+use parquet::{arrow::ArrowWriter, file::properties::WriterProperties};
+
+use crate::{
+    error::WarcParquetError,
+    output::{numbered_path, AtomicFile, ParquetSink},
+    url::Url,
+};
+
+type WriteResult<T> = Result<T, WarcParquetError>;
+
+/// The directory name Hive gives to rows whose partition value is null.
+const DEFAULT_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+/// The number of partition files kept open at once by default.
+const DEFAULT_MAX_OPEN_FILES: usize = 128;
+
+/// The number of bytes open partition files may buffer in all by default.
+const DEFAULT_MAX_BUFFERED_BYTES: usize = 256 * 1024 * 1024;
+
+/// A key by which [`PartitionedParquetWriter`] partitions rows.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use arrow::{
+///     array::{StringArray, TimestampMillisecondArray},
+///     datatypes::{DataType, Field, Schema, TimeUnit},
+///     record_batch::RecordBatch,
+/// };
+/// use tempfile::tempdir;
+/// use warc_parquet::{ParquetSink, PartitionKey, PartitionedParquetWriter, UTC};
+///
+/// # fn main() {
+/// let schema = Arc::new(Schema::new(vec![
+///     Field::new(
+///         "date",
+///         DataType::Timestamp(TimeUnit::Millisecond, Some(UTC.into())),
+///         true,
+///     ),
+///     Field::new("target_uri", DataType::Utf8, true),
+/// ]));
+/// let record_batch = RecordBatch::try_new(
+///     schema.clone(),
+///     vec![
+///         Arc::new(
+///             TimestampMillisecondArray::from(vec![Some(1_594_176_775_000), None])
+///                 .with_timezone(UTC),
+///         ),
+///         Arc::new(StringArray::from(vec![
+///             Some("http://user@Example.COM:8080/index.html"),
+///             None,
+///         ])),
+///     ],
+/// )
+/// .unwrap();
+///
+/// let directory = tempdir().unwrap();
+/// let mut writer = PartitionedParquetWriter::try_new(
+///     directory.path(),
+///     schema,
+///     &[PartitionKey::CrawlDate, PartitionKey::Host],
+///     None,
+/// )
+/// .unwrap();
+/// // Derived keys leave the columns they are derived from in the files.
+/// assert_eq!(writer.file_schema().fields().len(), 2);
+/// writer.write(&record_batch).unwrap();
+/// let paths = writer.close().unwrap();
+///
+/// assert_eq!(
+///     paths,
+///     [
+///         directory
+///             .path()
+///             .join("crawl_date=2020-07-08/host=example.com/part-00001.parquet"),
+///         directory.path().join(
+///             "crawl_date=__HIVE_DEFAULT_PARTITION__/host=__HIVE_DEFAULT_PARTITION__/part-00001.parquet"
+///         ),
+///     ]
+/// );
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PartitionKey {
+    /// The value of a column. The column is left out of the files written, as
+    /// its value is given by the directory they are written to.
+    Column(String),
+
+    /// The UTC day of the `date` column, e.g. `crawl_date=2020-07-08`.
+    CrawlDate,
+
+    /// The host of the `target_uri` column, lowercased and without its port,
+    /// e.g. `host=example.com`.
+    Host,
+}
+
+impl PartitionKey {
+    /// Returns the name the key is given in directory names.
+    pub fn name(&self) -> &str {
+        match self {
+            PartitionKey::Column(name) => name,
+            PartitionKey::CrawlDate => "crawl_date",
+            PartitionKey::Host => "host",
+        }
+    }
+}
+
+impl FromStr for PartitionKey {
+    type Err = Infallible;
+
+    /// Parses `crawl_date` and `host` as the derived keys of those names and
+    /// anything else as a column.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Ok(match name {
+            "crawl_date" => PartitionKey::CrawlDate,
+            "host" => PartitionKey::Host,
+            name => PartitionKey::Column(name.to_string()),
+        })
+    }
+}
+
+/// A partition key along with the column its values are taken from.
+struct ResolvedKey {
+    key: PartitionKey,
+    column: usize,
+}
+
+/// An open file of one partition.
+struct PartitionFile {
+    writer: ArrowWriter<AtomicFile>,
+    last_written: u64,
+}
+
+/// Writes Parquet as a Hive-style partitioned dataset, a directory tree with
+/// one level per [`PartitionKey`], e.g.
+/// `type=response/crawl_date=2020-07-08/part-00001.parquet`.
+///
+/// Partition values are escaped as Hive escapes them, so that a value may
+/// never name another directory, and null values are written to
+/// `__HIVE_DEFAULT_PARTITION__`. A derived key, such as
+/// [`PartitionKey::Host`], is taken from a column of the same name instead
+/// should the schema have one.
+///
+/// Each partition is written to a file of its own, so only so many are kept
+/// open at once. Once that many are, the least recently written is completed
+/// and further rows of its partition are written to a new file, numbered after
+/// it. Each open file buffers a row group in memory, so should the files
+/// together buffer more than a limit, the row groups of the least recently
+/// written are flushed until they no longer do.
+///
+/// Each file is written as an [`AtomicFile`], so files appear once complete.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use arrow::{
+///     array::{StringArray, UInt64Array},
+///     datatypes::{DataType, Field, Schema},
+///     record_batch::RecordBatch,
+/// };
+/// use tempfile::tempdir;
+/// use warc_parquet::{ParquetSink, PartitionKey, PartitionedParquetWriter};
+///
+/// # fn main() {
+/// let schema = Arc::new(Schema::new(vec![
+///     Field::new("type", DataType::Utf8, true),
+///     Field::new("n", DataType::UInt64, false),
+/// ]));
+/// let record_batch = RecordBatch::try_new(
+///     schema.clone(),
+///     vec![
+///         Arc::new(StringArray::from(vec!["request", "response", "request"])),
+///         Arc::new(UInt64Array::from_iter_values(0..3)),
+///     ],
+/// )
+/// .unwrap();
+///
+/// let directory = tempdir().unwrap();
+/// let mut writer = PartitionedParquetWriter::try_new(
+///     directory.path(),
+///     schema,
+///     &[PartitionKey::Column(String::from("type"))],
+///     None,
+/// )
+/// .unwrap();
+/// writer.write(&record_batch).unwrap();
+/// let paths = writer.close().unwrap();
+///
+/// assert_eq!(
+///     paths,
+///     [
+///         directory.path().join("type=request/part-00001.parquet"),
+///         directory.path().join("type=response/part-00001.parquet"),
+///     ]
+/// );
+/// # }
+/// ```
+pub struct PartitionedParquetWriter {
+    directory: PathBuf,
+    file_stem: String,
+    keys: Vec<ResolvedKey>,
+    file_schema: SchemaRef,
+    file_columns: Vec<usize>,
+    properties: Option<WriterProperties>,
+    max_open_files: usize,
+    max_buffered_bytes: usize,
+    files: HashMap<String, PartitionFile>,
+    file_counts: HashMap<String, usize>,
+    writes: u64,
+    paths: Vec<PathBuf>,
+}
+
+impl PartitionedParquetWriter {
+    /// Creates a writer of batches of the given schema into `directory`,
+    /// partitioned by `keys` in the order given.
+    ///
+    /// Fails with [`WarcParquetError::UnknownPartitionKey`] should a key name
+    /// no column of the schema, or a derived key lack the column it is derived
+    /// from.
+    pub fn try_new(
+        directory: impl Into<PathBuf>,
+        schema: SchemaRef,
+        keys: &[PartitionKey],
+        properties: Option<WriterProperties>,
+    ) -> WriteResult<Self> {
+        let mut resolved_keys = Vec::with_capacity(keys.len());
+        for key in keys {
+            let key = match key {
+                PartitionKey::CrawlDate | PartitionKey::Host
+                    if schema.index_of(key.name()).is_ok() =>
+                {
+                    PartitionKey::Column(key.name().to_string())
+                }
+                key => key.clone(),
+            };
+            let (source_column, source_type) = match &key {
+                PartitionKey::Column(name) => (name.as_str(), None),
+                PartitionKey::CrawlDate => ("date", None),
+                PartitionKey::Host => ("target_uri", Some(DataType::Utf8)),
+            };
+            let column = schema
+                .index_of(source_column)
+                .ok()
+                .filter(|&column| {
+                    source_type
+                        .as_ref()
+                        .is_none_or(|data_type| schema.field(column).data_type() == data_type)
+                })
+                .ok_or_else(|| WarcParquetError::UnknownPartitionKey(key.name().to_string()))?;
+            resolved_keys.push(ResolvedKey { key, column });
+        }
+
+        // Columns partitioned by are given by the directory and so left out.
+        let file_columns = (0..schema.fields().len())
+            .filter(|&index| {
+                !resolved_keys.iter().any(|resolved| {
+                    matches!(resolved.key, PartitionKey::Column(_)) && resolved.column == index
+                })
+            })
+            .collect::<Vec<_>>();
+        let file_schema = Arc::new(schema.project(&file_columns)?);
+
+        Ok(Self {
+            directory: directory.into(),
+            file_stem: String::from("part"),
+            keys: resolved_keys,
+            file_schema,
+            file_columns,
+            properties,
+            max_open_files: DEFAULT_MAX_OPEN_FILES,
+            max_buffered_bytes: DEFAULT_MAX_BUFFERED_BYTES,
+            files: HashMap::new(),
+            file_counts: HashMap::new(),
+            writes: 0,
+            paths: Vec::new(),
+        })
+    }
+
+    /// Sets the name files are given ahead of their number, `part` by
+    /// default.
+    pub fn with_file_stem(mut self, file_stem: impl Into<String>) -> Self {
+        self.file_stem = file_stem.into();
+        self
+    }
+
+    /// Sets the most files kept open at once, 128 by default.
+    pub fn with_max_open_files(mut self, max_open_files: usize) -> Self {
+        self.max_open_files = max_open_files.max(1);
+        self
+    }
+
+    /// Sets the most bytes the open files may buffer in all before row groups
+    /// are flushed, 256 MiB by default.
+    ///
+    /// Flushing early writes smaller row groups, however without a limit the
+    /// open files could together buffer as many row groups as
+    /// [`with_max_open_files`](Self::with_max_open_files) allows files.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::{fs::File, sync::Arc};
+    ///
+    /// use arrow::{
+    ///     array::StringArray,
+    ///     datatypes::{DataType, Field, Schema},
+    ///     record_batch::RecordBatch,
+    /// };
+    /// use parquet::file::reader::{FileReader, SerializedFileReader};
+    /// use tempfile::tempdir;
+    /// use warc_parquet::{ParquetSink, PartitionKey, PartitionedParquetWriter};
+    ///
+    /// # fn main() {
+    /// let schema = Arc::new(Schema::new(vec![
+    ///     Field::new("type", DataType::Utf8, true),
+    ///     Field::new("id", DataType::Utf8, true),
+    /// ]));
+    /// let record_batch = RecordBatch::try_new(
+    ///     schema.clone(),
+    ///     vec![
+    ///         Arc::new(StringArray::from(vec!["request", "response"])),
+    ///         Arc::new(StringArray::from(vec!["a", "b"])),
+    ///     ],
+    /// )
+    /// .unwrap();
+    ///
+    /// let directory = tempdir().unwrap();
+    /// let mut writer = PartitionedParquetWriter::try_new(
+    ///     directory.path(),
+    ///     schema,
+    ///     &[PartitionKey::Column(String::from("type"))],
+    ///     None,
+    /// )
+    /// .unwrap()
+    /// .with_max_buffered_bytes(1);
+    /// writer.write(&record_batch).unwrap();
+    /// writer.write(&record_batch).unwrap();
+    /// let paths = writer.close().unwrap();
+    ///
+    /// // Each batch was flushed as soon as it was written.
+    /// for path in paths {
+    ///     let reader = SerializedFileReader::new(File::open(path).unwrap()).unwrap();
+    ///     assert_eq!(reader.metadata().num_row_groups(), 2);
+    /// }
+    /// # }
+    /// ```
+    pub fn with_max_buffered_bytes(mut self, max_buffered_bytes: usize) -> Self {
+        self.max_buffered_bytes = max_buffered_bytes;
+        self
+    }
+
+    /// Returns the schema of the files written, that of the batches less the
+    /// columns partitioned by.
+    pub fn file_schema(&self) -> SchemaRef {
+        self.file_schema.clone()
+    }
+
+    /// Completes every open file, returning the paths of every file written
+    /// in order. Nothing is written if no rows were.
+    pub fn close(mut self) -> WriteResult<Vec<PathBuf>> {
+        let partitions = self.files.keys().cloned().collect::<Vec<_>>();
+        for partition in partitions {
+            self.close_file(&partition)?;
+        }
+        self.paths.sort();
+        Ok(self.paths)
+    }
+
+    /// Returns the relative directory of each row of the batch.
+    fn partitions(&self, record_batch: &RecordBatch) -> WriteResult<Vec<String>> {
+        let mut partitions = vec![String::new(); record_batch.num_rows()];
+        for resolved in &self.keys {
+            let column = record_batch.column(resolved.column);
+            let values: ArrayRef = match resolved.key {
+                PartitionKey::CrawlDate => cast(column, &DataType::Date32)?,
+                PartitionKey::Host => {
+                    let target_uris = column
+                        .as_any()
+                        .downcast_ref::<StringArray>()
+                        .expect("target_uri is checked to be Utf8");
+                    Arc::new(
+                        target_uris
+                            .iter()
+                            .map(|target_uri| target_uri.and_then(Url::parse)?.host())
+                            .collect::<StringArray>(),
+                    )
+                }
+                PartitionKey::Column(_) => column.clone(),
+            };
+
+            for (row, partition) in partitions.iter_mut().enumerate() {
+                if !partition.is_empty() {
+                    partition.push('/');
+                }
+                escape_into(partition, resolved.key.name());
+                partition.push('=');
+                let value = if values.is_null(row) {
+                    String::new()
+                } else {
+                    array_value_to_string(&values, row)?
+                };
+                if value.is_empty() {
+                    partition.push_str(DEFAULT_PARTITION);
+                } else {
+                    escape_into(partition, &value);
+                }
+            }
+        }
+
+        Ok(partitions)
+    }
+
+    /// Returns the writer of the partition's open file, opening one if need
+    /// be.
+    fn writer(&mut self, partition: &str) -> WriteResult<&mut ArrowWriter<AtomicFile>> {
+        if !self.files.contains_key(partition) {
+            if self.files.len() >= self.max_open_files {
+                let least_recent = self
+                    .files
+                    .iter()
+                    .min_by_key(|(_, file)| file.last_written)
+                    .map(|(partition, _)| partition.clone())
+                    .expect("at least one file is open");
+                self.close_file(&least_recent)?;
+            }
+
+            let file_count = self.file_counts.entry(partition.to_string()).or_default();
+            *file_count += 1;
+            let partition_directory = self.directory.join(partition);
+            fs::create_dir_all(&partition_directory)?;
+            let path = numbered_path(
+                &partition_directory.join(format!("{}.parquet", self.file_stem)),
+                *file_count,
+            );
+            let writer = ArrowWriter::try_new(
+                AtomicFile::create(path)?,
+                self.file_schema.clone(),
+                self.properties.clone(),
+            )?;
+            self.files.insert(
+                partition.to_string(),
+                PartitionFile {
+                    writer,
+                    last_written: 0,
+                },
+            );
+        }
+
+        self.writes += 1;
+        let file = self.files.get_mut(partition).expect("the file was opened");
+        file.last_written = self.writes;
+        Ok(&mut file.writer)
+    }
+
+    /// Flushes the row groups of the least recently written files until the
+    /// open files buffer no more than the limit.
+    fn limit_buffered_bytes(&mut self) -> WriteResult<()> {
+        let mut buffered_bytes: usize = self
+            .files
+            .values()
+            .map(|file| file.writer.in_progress_size())
+            .sum();
+        if buffered_bytes <= self.max_buffered_bytes {
+            return Ok(());
+        }
+
+        let mut files = self.files.values_mut().collect::<Vec<_>>();
+        files.sort_by_key(|file| file.last_written);
+        for file in files {
+            if buffered_bytes <= self.max_buffered_bytes {
+                break;
+            }
+            let file_bytes = file.writer.in_progress_size();
+            if file.writer.in_progress_rows() > 0 {
+                file.writer.flush()?;
+                buffered_bytes -= file_bytes;
+            }
+        }
+        Ok(())
+    }
+
+    /// Completes the partition's open file.
+    fn close_file(&mut self, partition: &str) -> WriteResult<()> {
+        if let Some(file) = self.files.remove(partition) {
+            let file = file.writer.into_inner()?;
+            let path = file.path().to_path_buf();
+            file.commit()?;
+            self.paths.push(path);
+        }
+        Ok(())
+    }
+}
+
+impl ParquetSink for PartitionedParquetWriter {
+    fn write(&mut self, record_batch: &RecordBatch) -> WriteResult<()> {
+        let partitions = self.partitions(record_batch)?;
+        let record_batch = record_batch.project(&self.file_columns)?;
+
+        // Rows are grouped by partition in the order each partition is first
+        // seen, keeping the order of the rows within each.
+        let mut rows: HashMap<&str, Vec<u32>> = HashMap::new();
+        let mut order = Vec::new();
+        for (row, partition) in partitions.iter().enumerate() {
+            rows.entry(partition)
+                .or_insert_with(|| {
+                    order.push(partition.as_str());
+                    Vec::new()
+                })
+                .push(row as u32);
+        }
+
+        for partition in order {
+            let partition_rows = &rows[partition];
+            let partition_batch = if partition_rows.len() == record_batch.num_rows() {
+                record_batch.clone()
+            } else {
+                let indices = UInt32Array::from(partition_rows.clone());
+                let columns = record_batch
+                    .columns()
+                    .iter()
+                    .map(|column| take(column, &indices, None))
+                    .collect::<Result<Vec<_>, _>>()?;
+                RecordBatch::try_new(self.file_schema.clone(), columns)?
+            };
+            self.writer(partition)?.write(&partition_batch)?;
+        }
+
+        self.limit_buffered_bytes()
+    }
+
+    fn flush(&mut self) -> WriteResult<()> {
+        for file in self.files.values_mut() {
+            if file.writer.in_progress_rows() > 0 {
+                file.writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Appends the partition key or value, escaping characters as Hive does.
+fn escape_into(partition: &mut String, value: &str) {
+    for c in value.chars() {
+        match c {
+            '\x00'..='\x1F'
+            | '"'
+            | '#'
+            | '%'
+            | '\''
+            | '*'
+            | '/'
+            | ':'
+            | '='
+            | '?'
+            | '\\'
+            | '\x7F'
+            | '{'
+            | '['
+            | ']'
+            | '^' => {
+                let _ = write!(partition, "%{:02X}", c as u32);
+            }
+            c => partition.push(c),
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::HashSet;
+
+use lazy_static::lazy_static;
+
+/// A snapshot of the [Public Suffix List](https://publicsuffix.org), spanning
+/// both its ICANN and private sections, as of the revision its header notes.
+const PUBLIC_SUFFIX_LIST: &str = include_str!("public_suffix_list.dat");
+
+lazy_static! {
+    /// The rules of the Public Suffix List, e.g. `com`, `*.ck` and `!www.ck`.
+    static ref RULES: HashSet<&'static str> = PUBLIC_SUFFIX_LIST
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .filter(|rule| !rule.starts_with("//"))
+        .collect();
+}
+
+/// Returns the registered domain of a lowercase host, the public suffix along
+/// with the label preceding it, e.g. `example.co.uk` for `www.example.co.uk`.
+///
+/// Labels encoded as punycode are decoded to match the list's rules, which are
+/// given in Unicode. There is no registered domain for a host which is itself
+/// a public suffix, nor for an IP address.
+pub(crate) fn registered_domain(host: &str) -> Option<&str> {
+    let host = host.strip_suffix('.').unwrap_or(host);
+    if host.is_empty() || host.starts_with('[') || is_ipv4(host) {
+        return None;
+    }
+
+    let labels = host
+        .split('.')
+        .map(|label| match label.strip_prefix("xn--") {
+            Some(encoded) => decode_punycode(encoded).unwrap_or_else(|| label.to_string()),
+            None => label.to_string(),
+        })
+        .collect::<Vec<_>>();
+    if labels.iter().any(String::is_empty) {
+        return None;
+    }
+
+    // Rules are tried from the longest suffix down, so the first to match is
+    // the one prevailing. Exception rules are longer than the wildcard rules
+    // they make exceptions to, so are tried first.
+    let mut suffix_labels = 1;
+    for start in 0..labels.len() {
+        let suffix = labels[start..].join(".");
+        if RULES.contains(format!("!{suffix}").as_str()) {
+            suffix_labels = labels.len() - start - 1;
+            break;
+        }
+        if RULES.contains(suffix.as_str()) {
+            suffix_labels = labels.len() - start;
+            break;
+        }
+        if start + 1 < labels.len() {
+            let parent = labels[start + 1..].join(".");
+            if RULES.contains(format!("*.{parent}").as_str()) {
+                suffix_labels = labels.len() - start;
+                break;
+            }
+        }
+    }
+
+    if labels.len() <= suffix_labels {
+        return None;
+    }
+    let domain_start = host
+        .rmatch_indices('.')
+        .nth(suffix_labels)
+        .map_or(0, |(index, _)| index + 1);
+    Some(&host[domain_start..])
+}
+
+/// Returns whether the host is a dotted IPv4 address.
+fn is_ipv4(host: &str) -> bool {
+    let parts = host.split('.').collect::<Vec<_>>();
+    parts.len() == 4 && parts.iter().all(|part| part.parse::<u8>().is_ok())
+}
+
+/// Decodes a punycode label, less its `xn--` prefix, per RFC 3492.
+fn decode_punycode(encoded: &str) -> Option<String> {
+    const BASE: u32 = 36;
+    const T_MIN: u32 = 1;
+    const T_MAX: u32 = 26;
+    const SKEW: u32 = 38;
+    const DAMP: u32 = 700;
+
+    let adapt = |mut delta: u32, num_points: u32, first_time: bool| {
+        delta /= if first_time { DAMP } else { 2 };
+        delta += delta / num_points;
+        let mut k = 0;
+        while delta > ((BASE - T_MIN) * T_MAX) / 2 {
+            delta /= BASE - T_MIN;
+            k += BASE;
+        }
+        k + (BASE - T_MIN + 1) * delta / (delta + SKEW)
+    };
+
+    let (basic, extended) = match encoded.rfind('-') {
+        Some(index) => (&encoded[..index], &encoded[index + 1..]),
+        None => ("", encoded),
+    };
+    if !basic.is_ascii() {
+        return None;
+    }
+    let mut output = basic.chars().collect::<Vec<_>>();
+
+    let mut n = 128u32;
+    let mut i = 0u32;
+    let mut bias = 72;
+    let mut digits = extended.bytes().peekable();
+    while digits.peek().is_some() {
+        let old_i = i;
+        let mut w = 1u32;
+        let mut k = BASE;
+        loop {
+            let digit = match digits.next()? {
+                byte @ b'a'..=b'z' => byte - b'a',
+                byte @ b'A'..=b'Z' => byte - b'A',
+                byte @ b'0'..=b'9' => byte - b'0' + 26,
+                _ => return None,
+            } as u32;
+            i = i.checked_add(digit.checked_mul(w)?)?;
+            let t = if k <= bias {
+                T_MIN
+            } else if k >= bias + T_MAX {
+                T_MAX
+            } else {
+                k - bias
+            };
+            if digit < t {
+                break;
+            }
+            w = w.checked_mul(BASE - t)?;
+            k += BASE;
+        }
+
+        let num_points = output.len() as u32 + 1;
+        bias = adapt(i - old_i, num_points, old_i == 0);
+        n = n.checked_add(i / num_points)?;
+        i %= num_points;
+        output.insert(i as usize, char::from_u32(n)?);
+        i += 1;
+    }
+
+    Some(output.into_iter().collect())
+}
@@ -1,18 +1,236 @@
-use std::{io::BufRead, sync::Arc};
-
-use time::{format_description::well_known::Iso8601, OffsetDateTime};
-use warc::{BufferedBody, Record, StreamingIter, WarcHeader, WarcReader};
+use std::{
+    io::{BufRead, Write},
+    path::PathBuf,
+    sync::Arc,
+};
 
 use crate::{
     arrow::{
-        array::{ArrayRef, BinaryArray, StringArray, TimestampMillisecondArray, UInt32Array},
-        datatypes::SchemaRef,
+        array::{Array, ArrayRef, BooleanArray},
+        compute::filter,
+        datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit},
+        error::ArrowError,
         record_batch::RecordBatch,
     },
-    schema::WARC_1_0_SCHEMA,
+    column::{is_header_type, ColumnSource, ExtractOptions, WarcColumn},
+    digest::{DigestAlgorithm, DigestEncoding},
+    error::WarcParquetError,
+    record::{BlockHandling, RawRecord, RecordStream},
+    schema::{UTC, WARC_1_0_SCHEMA},
+    source::{WarcCompression, WarcSource},
+    spill::BodySpill,
 };
 
-type ReaderResult<T> = Result<T, Box<dyn std::error::Error>>;
+type ReaderResult<T> = Result<T, WarcParquetError>;
+
+/// Determines how the reader responds to records it cannot translate, such as
+/// records missing mandatory headers or carrying unparseable values.
+///
+/// Records which are so malformed that their headers cannot be trusted at all,
+/// e.g. a truncated record or one lacking a `WARC/` version line, are never
+/// emitted as rows: every policy other than [`Fail`](ErrorPolicy::Fail) drops
+/// them.
+///
+/// # Example
+///
+/// ```rust
+/// use std::{
+///     fs,
+///     io::{BufReader, Cursor},
+/// };
+///
+/// use arrow::array::{Array, StringArray, TimestampMillisecondArray};
+/// use tempfile::NamedTempFile;
+/// use warc_parquet::{ErrorPolicy, WarcColumn, WarcParquetError, WarcToArrowReader};
+///
+/// # fn main() {
+/// let record = |id: &str, date: &str| {
+///     format!(
+///         "WARC/1.0\r\n\
+///          WARC-Type: resource\r\n\
+///          Content-Length: 0\r\n\
+///          WARC-Record-Id: <urn:test:error-policy:{id}>\r\n\
+///          WARC-Date: {date}\r\n\
+///          \r\n\
+///          \r\n\
+///          \r\n"
+///     )
+/// };
+/// let good_record = record("record-0", "2020-07-08T02:52:55Z");
+/// let bad_record = record("record-1", "yesterday");
+/// let warc_content = [good_record.as_str(), &bad_record, &good_record].concat();
+///
+/// let read = |error_policy| {
+///     let input = BufReader::new(Cursor::new(warc_content.clone()));
+///     let mut reader = WarcToArrowReader::builder(input)
+///         .with_columns(&[WarcColumn::Id, WarcColumn::Date])
+///         .with_error_policy(error_policy)
+///         .build()
+///         .unwrap();
+///     reader.iter_reader().collect::<Vec<_>>()
+/// };
+///
+/// // The record preceding the malformed one is returned before the error.
+/// let results = read(ErrorPolicy::Fail);
+/// assert_eq!(results.len(), 2);
+/// assert_eq!(results[0].as_ref().unwrap().num_rows(), 1);
+/// assert!(matches!(
+///     &results[1],
+///     Err(WarcParquetError::MalformedHeader { header, offset, .. })
+///         if header == "WARC-Date" && *offset == good_record.len() as u64
+/// ));
+///
+/// // The malformed record is kept, less its date.
+/// let results = read(ErrorPolicy::NullOut);
+/// let record_batch = results[0].as_ref().unwrap();
+/// let ids = record_batch
+///     .column(0)
+///     .as_any()
+///     .downcast_ref::<StringArray>()
+///     .unwrap();
+/// let dates = record_batch
+///     .column(1)
+///     .as_any()
+///     .downcast_ref::<TimestampMillisecondArray>()
+///     .unwrap();
+/// assert_eq!(ids.value(1), "<urn:test:error-policy:record-1>");
+/// assert_eq!(dates.null_count(), 1);
+/// assert!(dates.is_null(1));
+///
+/// // The malformed record is written to the sink as it was read.
+/// let quarantine = NamedTempFile::new().unwrap();
+/// let sink = Box::new(quarantine.reopen().unwrap());
+/// let results = read(ErrorPolicy::Quarantine(sink));
+/// assert_eq!(results[0].as_ref().unwrap().num_rows(), 2);
+/// assert_eq!(fs::read_to_string(quarantine.path()).unwrap(), bad_record);
+/// # }
+/// ```
+#[derive(Default)]
+pub enum ErrorPolicy {
+    /// Return the error and stop reading. Records read ahead of the malformed
+    /// one into the same batch are first returned as a batch of their own.
+    #[default]
+    Fail,
+
+    /// Drop the record and continue with the next.
+    Skip,
+
+    /// Emit the record, setting fields which cannot be extracted to null. All
+    /// fields of the reader's schema become nullable under this policy.
+    NullOut,
+
+    /// Drop the record and write its raw bytes to the given sink before
+    /// continuing with the next. The sink receives the records as valid WARC
+    /// framing, so it can be inspected with ordinary WARC tooling.
+    Quarantine(Box<dyn Write + Send>),
+}
+
+/// Determines how the reader stores record blocks, which may be arbitrarily
+/// large.
+///
+/// Arrow's `Binary` type addresses at most 2 GiB per column of a batch, so
+/// archives with large records call for one of the other policies.
+///
+/// Blocks are truncated or spilled as they are read, so the part of a block
+/// not stored is never held in memory. Columns derived from the block, such as
+/// the HTTP columns, see only the part stored, while the digest columns are
+/// null for blocks not stored whole. Records quarantined under
+/// [`ErrorPolicy::Quarantine`] are likewise written with the part stored.
+#[derive(Default)]
+pub enum BodyPolicy {
+    /// Store blocks as `Binary`.
+    #[default]
+    Binary,
+
+    /// Store blocks as `LargeBinary`, which addresses blocks totalling more
+    /// than 2 GiB per batch. The `payload` columns are stored likewise.
+    LargeBinary,
+
+    /// Store blocks as `Binary`, truncated to the given number of bytes. The
+    /// [`BodyTruncated`](WarcColumn::BodyTruncated) column flags blocks which
+    /// were cut short.
+    MaxBodyBytes(usize),
+
+    /// Write blocks larger than `threshold` bytes to a file in `directory`
+    /// rather than storing them. The [`BodyRef`](WarcColumn::BodyRef) column
+    /// locates each within that file, while the `body` column is null.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::io::{BufReader, Cursor};
+    ///
+    /// use arrow::array::{Array, BinaryArray, StructArray, UInt64Array};
+    /// use warc_parquet::{BodyPolicy, WarcColumn, WarcToArrowReader};
+    ///
+    /// # fn main() {
+    /// let warc_content = b"\
+    ///     WARC/1.0\r\n\
+    ///     WARC-Type: resource\r\n\
+    ///     Content-Length: 13\r\n\
+    ///     WARC-Record-Id: <urn:test:spill:record-0>\r\n\
+    ///     WARC-Date: 2020-07-08T02:52:55Z\r\n\
+    ///     \r\n\
+    ///     Hello, world!\r\n\
+    ///     \r\n\
+    ///     WARC/1.0\r\n\
+    ///     WARC-Type: resource\r\n\
+    ///     Content-Length: 2\r\n\
+    ///     WARC-Record-Id: <urn:test:spill:record-1>\r\n\
+    ///     WARC-Date: 2020-07-08T02:52:55Z\r\n\
+    ///     \r\n\
+    ///     Hi\r\n\
+    ///     \r\n\
+    /// ";
+    ///
+    /// let directory = tempfile::tempdir().unwrap();
+    /// let input = BufReader::new(Cursor::new(warc_content));
+    /// let mut reader = WarcToArrowReader::builder(input)
+    ///     .with_columns(&[WarcColumn::Body, WarcColumn::BodyRef])
+    ///     .with_body_policy(BodyPolicy::External {
+    ///         directory: directory.path().to_path_buf(),
+    ///         threshold: 4,
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// let record_batch = reader.iter_reader().next().unwrap().unwrap();
+    ///
+    /// let body = record_batch
+    ///     .column(0)
+    ///     .as_any()
+    ///     .downcast_ref::<BinaryArray>()
+    ///     .unwrap();
+    /// assert!(body.is_null(0));
+    /// assert_eq!(body.value(1), b"Hi");
+    ///
+    /// let body_ref = record_batch
+    ///     .column(1)
+    ///     .as_any()
+    ///     .downcast_ref::<StructArray>()
+    ///     .unwrap();
+    /// let path = body_ref.column_by_name("path").unwrap();
+    /// let path = arrow::array::as_string_array(path).value(0);
+    /// let length = body_ref
+    ///     .column_by_name("length")
+    ///     .unwrap()
+    ///     .as_any()
+    ///     .downcast_ref::<UInt64Array>()
+    ///     .unwrap()
+    ///     .value(0);
+    /// assert_eq!(length, 13);
+    /// assert!(body_ref.is_null(1));
+    ///
+    /// drop(reader);
+    /// assert_eq!(std::fs::read(path).unwrap(), b"Hello, world!");
+    /// # }
+    /// ```
+    External {
+        /// The directory the spill file is created in.
+        directory: PathBuf,
+        /// The size past which blocks are written to the spill file.
+        threshold: usize,
+    },
+}
 
 /// A builder used to constract [`WarcToArrowReader`] for a given reader of
 /// WARC.
@@ -20,6 +238,18 @@ pub struct WarcToArrowReaderBuilder<R: BufRead> {
     reader: R,
     schema: SchemaRef,
     batch_size: usize,
+    max_batch_bytes: Option<usize>,
+    error_policy: ErrorPolicy,
+    body_policy: BodyPolicy,
+    time_unit: Option<TimeUnit>,
+    legacy_lengths: bool,
+    compression: Option<WarcCompression>,
+    source_filename: Option<String>,
+    source_range: Option<(u64, u64)>,
+    header_columns: Vec<(String, Field)>,
+    max_decoded_payload_bytes: usize,
+    digest_algorithm: DigestAlgorithm,
+    digest_encoding: DigestEncoding,
 }
 
 impl<R: BufRead> WarcToArrowReaderBuilder<R> {
@@ -34,43 +264,826 @@ impl<R: BufRead> WarcToArrowReaderBuilder<R> {
     ///
     /// let input = BufReader::new(Cursor::new(b""));
     /// let reader_builder = WarcToArrowReaderBuilder::new(input).with_batch_size(1);
-    /// let reader = reader_builder.build();
+    /// let reader = reader_builder.build().unwrap();
     /// ```
     pub fn new(reader: R) -> Self {
         Self {
             reader,
             schema: WARC_1_0_SCHEMA.clone(),
             batch_size: 8192,
+            max_batch_bytes: None,
+            error_policy: ErrorPolicy::default(),
+            body_policy: BodyPolicy::default(),
+            time_unit: None,
+            legacy_lengths: false,
+            compression: None,
+            source_filename: None,
+            source_range: None,
+            header_columns: Vec::new(),
+            max_decoded_payload_bytes: 64 * 1024 * 1024,
+            digest_algorithm: DigestAlgorithm::default(),
+            digest_encoding: DigestEncoding::default(),
         }
     }
 
     /// Sets the schema for the reader.
+    ///
+    /// Each field must be named after one of the [`WarcColumn`]s.
     pub fn with_schema(mut self, schema: SchemaRef) -> Self {
         self.schema = schema;
         self
     }
 
+    /// Sets the schema for the reader to one comprising the given columns, in
+    /// the given order.
+    pub fn with_columns(mut self, columns: &[WarcColumn]) -> Self {
+        self.schema = WarcColumn::schema(columns);
+        self
+    }
+
+    /// Adds a column holding the value of the given header, following the
+    /// fields of the schema.
+    ///
+    /// The value is parsed as the given type, which may be any type Arrow can
+    /// cast strings to. Timestamps are parsed as ISO 8601 dates, like
+    /// `WARC-Date`. Values which fail to parse are handled according to the
+    /// reader's [`ErrorPolicy`]. The column is null for records lacking the
+    /// header.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::io::{BufReader, Cursor};
+    ///
+    /// use arrow::{array::UInt64Array, datatypes::DataType};
+    /// use warc_parquet::{WarcColumn, WarcToArrowReader};
+    ///
+    /// # fn main() {
+    /// let warc_content = b"\
+    ///     WARC/1.1\r\n\
+    ///     WARC-Type: response\r\n\
+    ///     Content-Length: 0\r\n\
+    ///     WARC-Record-Id: <urn:test:header-column:record-0>\r\n\
+    ///     WARC-Date: 2020-07-08T02:52:55Z\r\n\
+    ///     WARC-Page-ID: 42\r\n\
+    ///     \r\n\
+    ///     \r\n\
+    ///     \r\n\
+    /// ";
+    ///
+    /// let input = BufReader::new(Cursor::new(warc_content));
+    /// let mut reader = WarcToArrowReader::builder(input)
+    ///     .with_columns(&[WarcColumn::Id])
+    ///     .with_header_column("WARC-Page-ID", "page_id", DataType::UInt64)
+    ///     .build()
+    ///     .unwrap();
+    /// let record_batch = reader.iter_reader().next().unwrap().unwrap();
+    ///
+    /// assert_eq!(
+    ///     record_batch
+    ///         .column_by_name("page_id")
+    ///         .unwrap()
+    ///         .as_any()
+    ///         .downcast_ref::<UInt64Array>()
+    ///         .unwrap()
+    ///         .value(0),
+    ///     42
+    /// );
+    /// # }
+    /// ```
+    pub fn with_header_column(
+        mut self,
+        header: impl Into<String>,
+        name: impl Into<String>,
+        data_type: DataType,
+    ) -> Self {
+        self.header_columns
+            .push((header.into(), Field::new(name, data_type, true)));
+        self
+    }
+
+    /// Sets the size past which decoding an HTTP body for the
+    /// [`DecodedPayload`](WarcColumn::DecodedPayload) column is abandoned,
+    /// guarding against decompression bombs. Defaults to 64 MiB.
+    ///
+    /// A body which would decode past the limit is left undecoded, with the
+    /// [`PayloadDecodeFailed`](WarcColumn::PayloadDecodeFailed) column set.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::io::{BufReader, Cursor, Write};
+    ///
+    /// use arrow::array::{Array, BinaryArray, BooleanArray};
+    /// use libflate::gzip::Encoder;
+    /// use warc_parquet::{WarcColumn, WarcToArrowReader};
+    ///
+    /// # fn main() {
+    /// // A megabyte of zeroes compresses to around a kilobyte.
+    /// let mut encoder = Encoder::new(Vec::new()).unwrap();
+    /// encoder.write_all(&vec![0; 1024 * 1024]).unwrap();
+    /// let gzipped = encoder.finish().into_result().unwrap();
+    ///
+    /// let block = [
+    ///     b"HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\n\r\n".as_slice(),
+    ///     &gzipped,
+    /// ]
+    /// .concat();
+    /// let header_block = format!(
+    ///     "WARC/1.0\r\n\
+    ///      WARC-Type: response\r\n\
+    ///      Content-Length: {}\r\n\
+    ///      WARC-Record-Id: <urn:test:max-decoded-payload-bytes:record-0>\r\n\
+    ///      WARC-Date: 2020-07-08T02:52:55Z\r\n\
+    ///      Content-Type: application/http; msgtype=response\r\n\
+    ///      \r\n",
+    ///     block.len()
+    /// );
+    /// let warc_content = [header_block.as_bytes(), &block, b"\r\n\r\n"].concat();
+    ///
+    /// let decode = |max_decoded_payload_bytes| {
+    ///     let input = BufReader::new(Cursor::new(warc_content.clone()));
+    ///     let mut reader = WarcToArrowReader::builder(input)
+    ///         .with_columns(&[WarcColumn::DecodedPayload, WarcColumn::PayloadDecodeFailed])
+    ///         .with_max_decoded_payload_bytes(max_decoded_payload_bytes)
+    ///         .build()
+    ///         .unwrap();
+    ///     reader.iter_reader().next().unwrap().unwrap()
+    /// };
+    ///
+    /// let record_batch = decode(64 * 1024);
+    /// let decoded_payloads = record_batch
+    ///     .column(0)
+    ///     .as_any()
+    ///     .downcast_ref::<BinaryArray>()
+    ///     .unwrap();
+    /// let decode_failed = record_batch
+    ///     .column(1)
+    ///     .as_any()
+    ///     .downcast_ref::<BooleanArray>()
+    ///     .unwrap();
+    /// assert!(decoded_payloads.is_null(0));
+    /// assert!(decode_failed.value(0));
+    ///
+    /// let record_batch = decode(1024 * 1024);
+    /// let decoded_payloads = record_batch
+    ///     .column(0)
+    ///     .as_any()
+    ///     .downcast_ref::<BinaryArray>()
+    ///     .unwrap();
+    /// assert_eq!(decoded_payloads.value(0).len(), 1024 * 1024);
+    /// # }
+    /// ```
+    pub fn with_max_decoded_payload_bytes(mut self, max_decoded_payload_bytes: usize) -> Self {
+        self.max_decoded_payload_bytes = max_decoded_payload_bytes;
+        self
+    }
+
+    /// Sets the algorithm the
+    /// [`ComputedBlockDigest`](WarcColumn::ComputedBlockDigest) and
+    /// [`ComputedPayloadDigest`](WarcColumn::ComputedPayloadDigest) columns
+    /// are computed with. Defaults to [`DigestAlgorithm::Sha1`], as Heritrix
+    /// computes digests.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::io::{BufReader, Cursor};
+    ///
+    /// use arrow::array::StringArray;
+    /// use warc_parquet::{DigestAlgorithm, DigestEncoding, WarcColumn, WarcToArrowReader};
+    ///
+    /// # fn main() {
+    /// let warc_content = b"\
+    ///     WARC/1.0\r\n\
+    ///     WARC-Type: resource\r\n\
+    ///     Content-Length: 13\r\n\
+    ///     WARC-Record-Id: <urn:test:computed-digest:record-0>\r\n\
+    ///     WARC-Date: 2020-07-08T02:52:55Z\r\n\
+    ///     \r\n\
+    ///     Hello, world!\r\n\
+    ///     \r\n\
+    /// ";
+    ///
+    /// let computed_block_digest = |algorithm, encoding| {
+    ///     let input = BufReader::new(Cursor::new(warc_content));
+    ///     let mut reader = WarcToArrowReader::builder(input)
+    ///         .with_columns(&[WarcColumn::ComputedBlockDigest])
+    ///         .with_digest_algorithm(algorithm)
+    ///         .with_digest_encoding(encoding)
+    ///         .build()
+    ///         .unwrap();
+    ///     let record_batch = reader.iter_reader().next().unwrap().unwrap();
+    ///     record_batch
+    ///         .column(0)
+    ///         .as_any()
+    ///         .downcast_ref::<StringArray>()
+    ///         .unwrap()
+    ///         .value(0)
+    ///         .to_string()
+    /// };
+    ///
+    /// assert_eq!(
+    ///     computed_block_digest(DigestAlgorithm::Sha1, DigestEncoding::Base32),
+    ///     "sha1:SQ5HALIG6NCZTLXB7DNI56PXFFQDDVUZ"
+    /// );
+    /// assert_eq!(
+    ///     computed_block_digest(DigestAlgorithm::Sha256, DigestEncoding::Hex),
+    ///     "sha256:315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3"
+    /// );
+    /// assert_eq!(
+    ///     computed_block_digest(DigestAlgorithm::Sha1, DigestEncoding::Base64),
+    ///     "sha1:lDpwLQbzRZmu4fjajvn3KWAx1pk="
+    /// );
+    /// # }
+    /// ```
+    pub fn with_digest_algorithm(mut self, digest_algorithm: DigestAlgorithm) -> Self {
+        self.digest_algorithm = digest_algorithm;
+        self
+    }
+
+    /// Sets the encoding the
+    /// [`ComputedBlockDigest`](WarcColumn::ComputedBlockDigest) and
+    /// [`ComputedPayloadDigest`](WarcColumn::ComputedPayloadDigest) columns
+    /// are written in. Defaults to [`DigestEncoding::Base32`], as Heritrix
+    /// writes digests.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::io::{BufReader, Cursor};
+    ///
+    /// use arrow::array::{Array, StringArray};
+    /// use warc_parquet::{DigestAlgorithm, DigestEncoding, WarcColumn, WarcToArrowReader};
+    ///
+    /// # fn main() {
+    /// let warc_content = b"\
+    ///     WARC/1.0\r\n\
+    ///     WARC-Type: response\r\n\
+    ///     Content-Length: 21\r\n\
+    ///     WARC-Record-Id: <urn:test:digest-encoding:record-0>\r\n\
+    ///     WARC-Date: 2020-07-08T02:52:55Z\r\n\
+    ///     Content-Type: application/http; msgtype=response\r\n\
+    ///     \r\n\
+    ///     HTTP/1.1 200 OK\r\n\
+    ///     \r\n\
+    ///     Hi\r\n\
+    ///     \r\n\
+    ///     WARC/1.0\r\n\
+    ///     WARC-Type: response\r\n\
+    ///     Content-Length: 7\r\n\
+    ///     WARC-Record-Id: <urn:test:digest-encoding:record-1>\r\n\
+    ///     WARC-Date: 2020-07-08T02:52:55Z\r\n\
+    ///     Content-Type: application/http; msgtype=response\r\n\
+    ///     \r\n\
+    ///     garbage\r\n\
+    ///     \r\n\
+    /// ";
+    ///
+    /// let computed_payload_digest = |algorithm, encoding| {
+    ///     let input = BufReader::new(Cursor::new(warc_content));
+    ///     let mut reader = WarcToArrowReader::builder(input)
+    ///         .with_columns(&[WarcColumn::ComputedPayloadDigest])
+    ///         .with_digest_algorithm(algorithm)
+    ///         .with_digest_encoding(encoding)
+    ///         .build()
+    ///         .unwrap();
+    ///     let record_batch = reader.iter_reader().next().unwrap().unwrap();
+    ///     record_batch
+    ///         .column(0)
+    ///         .as_any()
+    ///         .downcast_ref::<StringArray>()
+    ///         .unwrap()
+    ///         .clone()
+    /// };
+    ///
+    /// let hex = computed_payload_digest(DigestAlgorithm::Md5, DigestEncoding::Hex);
+    /// assert_eq!(hex.value(0), "md5:c1a5298f939e87e8f962a5edfc206918");
+    /// let base64 = computed_payload_digest(DigestAlgorithm::Sha256, DigestEncoding::Base64);
+    /// assert_eq!(
+    ///     base64.value(0),
+    ///     "sha256:NjnvzQirsnOxYZ6C54wpp98CwQUbGCDpn8OV3KozJrg="
+    /// );
+    /// // The second record's HTTP message is malformed, so its payload is
+    /// // unknown.
+    /// assert!(hex.is_null(1));
+    /// # }
+    /// ```
+    pub fn with_digest_encoding(mut self, digest_encoding: DigestEncoding) -> Self {
+        self.digest_encoding = digest_encoding;
+        self
+    }
+
+    /// Sets the compression of the WARC source, which the reader decompresses.
+    /// By default the compression is detected from the magic number the
+    /// source begins with.
+    ///
+    /// Decompressing within the reader, rather than handing it decompressed
+    /// WARC, lets the [`WarcOffset`](WarcColumn::WarcOffset) and
+    /// [`WarcLength`](WarcColumn::WarcLength) columns refer to the member
+    /// holding each record, as is needed to read records back from the
+    /// compressed file.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::io::{BufReader, Cursor, Write};
+    ///
+    /// use arrow::array::UInt64Array;
+    /// use libflate::gzip::Encoder;
+    /// use warc_parquet::{WarcColumn, WarcCompression, WarcParquetError, WarcToArrowReader};
+    ///
+    /// # fn main() {
+    /// let record = |id| {
+    ///     let mut encoder = Encoder::new(Vec::new()).unwrap();
+    ///     write!(
+    ///         encoder,
+    ///         "WARC/1.0\r\n\
+    ///          WARC-Type: resource\r\n\
+    ///          Content-Length: 0\r\n\
+    ///          WARC-Record-Id: <urn:test:gzipped:record-{id}>\r\n\
+    ///          WARC-Date: 2020-07-08T02:52:55Z\r\n\
+    ///          \r\n\
+    ///          \r\n\
+    ///          \r\n"
+    ///     )
+    ///     .unwrap();
+    ///     encoder.finish().into_result().unwrap()
+    /// };
+    /// let (first, second) = (record(0), record(1));
+    /// let warc_content = [first.as_slice(), second.as_slice()].concat();
+    ///
+    /// let input = BufReader::new(Cursor::new(warc_content));
+    /// let mut reader = WarcToArrowReader::builder(input)
+    ///     .with_compression(WarcCompression::Gzip)
+    ///     .with_columns(&[WarcColumn::WarcOffset, WarcColumn::WarcLength])
+    ///     .build()
+    ///     .unwrap();
+    /// let record_batch = reader.iter_reader().next().unwrap().unwrap();
+    ///
+    /// let column = |index| {
+    ///     record_batch
+    ///         .column(index)
+    ///         .as_any()
+    ///         .downcast_ref::<UInt64Array>()
+    ///         .unwrap()
+    ///         .clone()
+    /// };
+    /// let first_length = first.len() as u64;
+    /// let second_length = second.len() as u64;
+    /// assert_eq!(column(0), UInt64Array::from(vec![0, first_length]));
+    /// assert_eq!(column(1), UInt64Array::from(vec![first_length, second_length]));
+    ///
+    /// // The compression given is used in place of that detected, here reading
+    /// // the gzip members as though they were WARC.
+    /// let warc_content = [first.as_slice(), second.as_slice()].concat();
+    /// let input = BufReader::new(Cursor::new(warc_content));
+    /// let mut reader = WarcToArrowReader::builder(input)
+    ///     .with_compression(WarcCompression::Uncompressed)
+    ///     .build()
+    ///     .unwrap();
+    /// assert!(matches!(
+    ///     reader.iter_reader().next().unwrap(),
+    ///     Err(WarcParquetError::MalformedRecord { offset: 0, .. })
+    /// ));
+    /// # }
+    /// ```
+    pub fn with_compression(mut self, compression: WarcCompression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Sets the name of the file the WARC source was read from, which the
+    /// [`WarcFilename`](WarcColumn::WarcFilename) column holds.
+    pub fn with_source_filename(mut self, source_filename: impl Into<String>) -> Self {
+        self.source_filename = Some(source_filename.into());
+        self
+    }
+
+    /// Restricts the reader to records beginning between the given offsets of
+    /// a file, for a reader positioned at the first of them.
+    pub(crate) fn with_source_range(mut self, source_start: u64, source_limit: u64) -> Self {
+        self.source_range = Some((source_start, source_limit));
+        self
+    }
+
     /// Sets the batch size for the reader.
     pub fn with_batch_size(mut self, batch_size: usize) -> Self {
         self.batch_size = batch_size;
         self
     }
 
+    /// Sets the most bytes of record headers and blocks a batch may hold, so
+    /// that a batch closes once either this or the batch size is reached.
+    /// Batches hold at least one record however large it is.
+    ///
+    /// Bounding batches by size keeps memory use predictable for archives
+    /// whose records vary widely in size. Blocks are only counted when the
+    /// reader's columns make use of them.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::io::{BufReader, Cursor};
+    ///
+    /// use warc_parquet::WarcToArrowReader;
+    ///
+    /// # fn main() {
+    /// let warc_content = b"\
+    ///     WARC/1.0\r\n\
+    ///     WARC-Type: resource\r\n\
+    ///     Content-Length: 13\r\n\
+    ///     WARC-Record-Id: <urn:test:batch-bytes:record-0>\r\n\
+    ///     WARC-Date: 2020-07-08T02:52:55Z\r\n\
+    ///     \r\n\
+    ///     Hello, world!\r\n\
+    ///     \r\n\
+    ///     WARC/1.0\r\n\
+    ///     WARC-Type: resource\r\n\
+    ///     Content-Length: 13\r\n\
+    ///     WARC-Record-Id: <urn:test:batch-bytes:record-1>\r\n\
+    ///     WARC-Date: 2020-07-08T02:52:55Z\r\n\
+    ///     \r\n\
+    ///     Hello, again!\r\n\
+    ///     \r\n\
+    /// ";
+    ///
+    /// let input = BufReader::new(Cursor::new(warc_content));
+    /// let mut reader = WarcToArrowReader::builder(input)
+    ///     .with_max_batch_bytes(1)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let record_batches = reader.iter_reader().collect::<Result<Vec<_>, _>>().unwrap();
+    /// assert_eq!(record_batches.len(), 2);
+    /// assert!(record_batches.iter().all(|record_batch| record_batch.num_rows() == 1));
+    /// # }
+    /// ```
+    pub fn with_max_batch_bytes(mut self, max_batch_bytes: usize) -> Self {
+        self.max_batch_bytes = Some(max_batch_bytes);
+        self
+    }
+
+    /// Sets how the reader handles malformed records. Defaults to
+    /// [`ErrorPolicy::Fail`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::io::{BufReader, Cursor};
+    ///
+    /// use warc_parquet::{ErrorPolicy, WarcToArrowReader};
+    ///
+    /// # fn main() {
+    /// let warc_content = b"\
+    ///     WARC/1.0\r\n\
+    ///     WARC-Type: response\r\n\
+    ///     Content-Length: 0\r\n\
+    ///     WARC-Record-Id: <urn:test:policy:record-0>\r\n\
+    ///     WARC-Date: yesterday\r\n\
+    ///     \r\n\
+    ///     \r\n\
+    ///     \r\n\
+    ///     WARC/1.0\r\n\
+    ///     WARC-Type: response\r\n\
+    ///     Content-Length: 0\r\n\
+    ///     WARC-Record-Id: <urn:test:policy:record-1>\r\n\
+    ///     WARC-Date: 2020-07-08T02:52:55Z\r\n\
+    ///     \r\n\
+    ///     \r\n\
+    ///     \r\n\
+    /// ";
+    ///
+    /// let input = BufReader::new(Cursor::new(warc_content));
+    /// let mut reader = WarcToArrowReader::builder(input)
+    ///     .with_error_policy(ErrorPolicy::Skip)
+    ///     .build()
+    ///     .unwrap();
+    /// let mut iter_reader = reader.iter_reader();
+    ///
+    /// let record_batch = iter_reader.next().unwrap().unwrap();
+    /// assert_eq!(record_batch.num_rows(), 1);
+    /// assert_eq!(iter_reader.records_skipped(), 1);
+    /// # }
+    /// ```
+    pub fn with_error_policy(mut self, error_policy: ErrorPolicy) -> Self {
+        self.error_policy = error_policy;
+        self
+    }
+
+    /// Sets how the reader stores record blocks. Defaults to
+    /// [`BodyPolicy::Binary`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::io::{BufReader, Cursor};
+    ///
+    /// use arrow::array::{BinaryArray, BooleanArray};
+    /// use warc_parquet::{BodyPolicy, WarcColumn, WarcToArrowReader};
+    ///
+    /// # fn main() {
+    /// let warc_content = b"\
+    ///     WARC/1.0\r\n\
+    ///     WARC-Type: resource\r\n\
+    ///     Content-Length: 13\r\n\
+    ///     WARC-Record-Id: <urn:test:body-policy:record-0>\r\n\
+    ///     WARC-Date: 2020-07-08T02:52:55Z\r\n\
+    ///     \r\n\
+    ///     Hello, world!\r\n\
+    ///     \r\n\
+    /// ";
+    ///
+    /// let input = BufReader::new(Cursor::new(warc_content));
+    /// let mut reader = WarcToArrowReader::builder(input)
+    ///     .with_columns(&[WarcColumn::Body, WarcColumn::BodyTruncated])
+    ///     .with_body_policy(BodyPolicy::MaxBodyBytes(5))
+    ///     .build()
+    ///     .unwrap();
+    /// let record_batch = reader.iter_reader().next().unwrap().unwrap();
+    ///
+    /// let body = record_batch.column(0).as_any().downcast_ref::<BinaryArray>();
+    /// assert_eq!(body.unwrap(), &BinaryArray::from_vec(vec![b"Hello"]));
+    /// let body_truncated = record_batch.column(1).as_any().downcast_ref::<BooleanArray>();
+    /// assert_eq!(body_truncated.unwrap(), &BooleanArray::from(vec![true]));
+    /// # }
+    /// ```
+    pub fn with_body_policy(mut self, body_policy: BodyPolicy) -> Self {
+        self.body_policy = body_policy;
+        self
+    }
+
+    /// Sets the precision with which date fields are stored, overriding that
+    /// of the schema. WARC 1.0 dates have second precision, however WARC 1.1
+    /// permits dates with up to nanosecond precision. Finer units are truncated
+    /// to the chosen one.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::io::{BufReader, Cursor};
+    ///
+    /// use arrow::{array::TimestampNanosecondArray, datatypes::TimeUnit};
+    /// use warc_parquet::WarcToArrowReader;
+    ///
+    /// # fn main() {
+    /// let warc_content = b"\
+    ///     WARC/1.1\r\n\
+    ///     WARC-Type: resource\r\n\
+    ///     Content-Length: 0\r\n\
+    ///     WARC-Record-Id: <urn:test:time-unit:record-0>\r\n\
+    ///     WARC-Date: 2020-07-08T02:52:55.123456789Z\r\n\
+    ///     \r\n\
+    ///     \r\n\
+    ///     \r\n\
+    /// ";
+    ///
+    /// let input = BufReader::new(Cursor::new(warc_content));
+    /// let mut reader = WarcToArrowReader::builder(input)
+    ///     .with_time_unit(TimeUnit::Nanosecond)
+    ///     .build()
+    ///     .unwrap();
+    /// let record_batch = reader.iter_reader().next().unwrap().unwrap();
+    ///
+    /// assert_eq!(
+    ///     record_batch
+    ///         .column_by_name("date")
+    ///         .unwrap()
+    ///         .as_any()
+    ///         .downcast_ref::<TimestampNanosecondArray>()
+    ///         .unwrap()
+    ///         .value(0),
+    ///     1_594_176_775_123_456_789
+    /// );
+    /// # }
+    /// ```
+    pub fn with_time_unit(mut self, time_unit: TimeUnit) -> Self {
+        self.time_unit = Some(time_unit);
+        self
+    }
+
+    /// Sets whether `content_length` and `segment_total_length` are stored as
+    /// `UInt32`, as they were before lengths were widened to `UInt64`, for
+    /// consumers expecting the narrower type. Records with lengths too large
+    /// for a 32-bit field are handled according to the reader's
+    /// [`ErrorPolicy`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::io::{BufReader, Cursor};
+    ///
+    /// use arrow::array::UInt32Array;
+    /// use warc_parquet::{WarcColumn, WarcParquetError, WarcToArrowReader};
+    ///
+    /// # fn main() {
+    /// // Some older writers count the CRLF following the block in its length.
+    /// let legacy_record = b"\
+    ///     WARC/1.0\r\n\
+    ///     WARC-Type: resource\r\n\
+    ///     Content-Length: 15\r\n\
+    ///     WARC-Record-Id: <urn:test:legacy-lengths:record-0>\r\n\
+    ///     WARC-Date: 2020-07-08T02:52:55Z\r\n\
+    ///     \r\n\
+    ///     Hello, world!\r\n\
+    ///     \r\n\
+    /// ";
+    /// // A continuation of a record of 5 GB, too large for a 32-bit field.
+    /// let segment_record = b"\
+    ///     WARC/1.0\r\n\
+    ///     WARC-Type: continuation\r\n\
+    ///     Content-Length: 2\r\n\
+    ///     WARC-Record-Id: <urn:test:legacy-lengths:record-1>\r\n\
+    ///     WARC-Date: 2020-07-08T02:52:55Z\r\n\
+    ///     WARC-Segment-Origin-ID: <urn:test:legacy-lengths:origin>\r\n\
+    ///     WARC-Segment-Number: 2\r\n\
+    ///     WARC-Segment-Total-Length: 5000000000\r\n\
+    ///     \r\n\
+    ///     Hi\r\n\
+    ///     \r\n\
+    /// ";
+    ///
+    /// let input = BufReader::new(Cursor::new(legacy_record));
+    /// let mut reader = WarcToArrowReader::builder(input)
+    ///     .with_legacy_lengths(true)
+    ///     .build()
+    ///     .unwrap();
+    /// let record_batch = reader.iter_reader().next().unwrap().unwrap();
+    /// assert_eq!(record_batch.num_rows(), 1);
+    /// let content_lengths = record_batch
+    ///     .column_by_name("content_length")
+    ///     .unwrap()
+    ///     .as_any()
+    ///     .downcast_ref::<UInt32Array>()
+    ///     .unwrap();
+    /// assert_eq!(content_lengths.value(0), 15);
+    ///
+    /// let warc_content = [legacy_record.as_slice(), segment_record].concat();
+    /// let input = BufReader::new(Cursor::new(warc_content));
+    /// let mut reader = WarcToArrowReader::builder(input)
+    ///     .with_columns(&[WarcColumn::Id, WarcColumn::SegmentTotalLength])
+    ///     .with_legacy_lengths(true)
+    ///     .build()
+    ///     .unwrap();
+    /// // The first record is returned ahead of the error.
+    /// let err = reader.iter_reader().nth(1).unwrap().unwrap_err();
+    /// assert!(matches!(
+    ///     &err,
+    ///     WarcParquetError::LengthOverflow {
+    ///         header,
+    ///         value: 5_000_000_000,
+    ///         ..
+    ///     } if header == "WARC-Segment-Total-Length"
+    /// ));
+    /// assert_eq!(err.offset(), Some(legacy_record.len() as u64));
+    /// # }
+    /// ```
+    pub fn with_legacy_lengths(mut self, legacy_lengths: bool) -> Self {
+        self.legacy_lengths = legacy_lengths;
+        self
+    }
+
     /// Build a [`WarcToArrowReader`].
-    pub fn build(self) -> WarcToArrowReader<R> {
-        let reader = WarcReader::new(self.reader);
-        WarcToArrowReader {
-            reader,
-            schema: self.schema,
-            batch_size: self.batch_size,
+    ///
+    /// Fails with [`WarcParquetError::UnknownField`] if the schema contains a
+    /// field the reader does not know how to populate, or with
+    /// [`WarcParquetError::Arrow`] if a header column clashes with a field of
+    /// the schema or has a type header values cannot be parsed as. Fails with
+    /// [`WarcParquetError::Io`] if the spill file of
+    /// [`BodyPolicy::External`] cannot be created.
+    pub fn build(self) -> ReaderResult<WarcToArrowReader<R>> {
+        let mut columns = self
+            .schema
+            .fields()
+            .iter()
+            .map(|field| {
+                WarcColumn::from_name(field.name())
+                    .map(ColumnSource::Warc)
+                    .ok_or_else(|| WarcParquetError::UnknownField(field.name().clone()))
+            })
+            .collect::<ReaderResult<Vec<_>>>()?;
+
+        let mut fields: Vec<Field> = self
+            .schema
+            .fields()
+            .iter()
+            .map(|field| field.as_ref().clone())
+            .collect();
+        for (header, field) in self.header_columns {
+            if fields.iter().any(|other| other.name() == field.name()) {
+                return Err(ArrowError::SchemaError(format!(
+                    "Header column {:?} clashes with another field.",
+                    field.name()
+                ))
+                .into());
+            }
+            if !is_header_type(field.data_type()) {
+                return Err(ArrowError::SchemaError(format!(
+                    "Header column {:?} cannot be of type {}.",
+                    field.name(),
+                    field.data_type()
+                ))
+                .into());
+            }
+            columns.push(ColumnSource::Header(header));
+            fields.push(field);
         }
+
+        // Record blocks are only held onto if something will make use of them.
+        let keep_body = columns.iter().any(ColumnSource::needs_body)
+            || matches!(self.error_policy, ErrorPolicy::Quarantine(_));
+
+        let null_out = matches!(self.error_policy, ErrorPolicy::NullOut);
+        let fields: Vec<Field> = fields
+            .into_iter()
+            .zip(&columns)
+            .map(|(mut field, column)| {
+                if let (Some(time_unit), DataType::Timestamp(..)) =
+                    (&self.time_unit, field.data_type())
+                {
+                    field = field
+                        .with_data_type(DataType::Timestamp(time_unit.clone(), Some(UTC.into())));
+                }
+                if self.legacy_lengths
+                    && matches!(
+                        column,
+                        ColumnSource::Warc(
+                            WarcColumn::ContentLength | WarcColumn::SegmentTotalLength
+                        )
+                    )
+                {
+                    field = field.with_data_type(DataType::UInt32);
+                }
+                if let (BodyPolicy::LargeBinary, DataType::Binary) =
+                    (&self.body_policy, field.data_type())
+                {
+                    if let ColumnSource::Warc(
+                        WarcColumn::Body | WarcColumn::Payload | WarcColumn::DecodedPayload,
+                    ) = column
+                    {
+                        field = field.with_data_type(DataType::LargeBinary);
+                    }
+                }
+                let nullable = field.is_nullable() || null_out;
+                field.with_nullable(nullable)
+            })
+            .collect();
+        let schema = Arc::new(Schema::new_with_metadata(
+            fields,
+            self.schema.metadata().clone(),
+        ));
+
+        let block_handling = match (&self.body_policy, keep_body) {
+            (_, false) => BlockHandling::Skip,
+            (
+                BodyPolicy::External {
+                    directory,
+                    threshold,
+                },
+                true,
+            ) => BlockHandling::Spill(BodySpill::create(directory, *threshold)?),
+            (BodyPolicy::MaxBodyBytes(max_body_bytes), true) => {
+                BlockHandling::Keep(Some(*max_body_bytes))
+            }
+            (_, true) => BlockHandling::Keep(None),
+        };
+
+        let mut source = match self.compression {
+            Some(compression) => WarcSource::new(self.reader, compression),
+            None => WarcSource::detect(self.reader)?,
+        };
+        let stream = match self.source_range {
+            Some((source_start, source_limit)) => {
+                source = source.with_source_start(source_start);
+                RecordStream::new(source, block_handling).with_source_limit(source_limit)
+            }
+            None => RecordStream::new(source, block_handling),
+        };
+        let spill_path = stream.spill_path().map(str::to_string);
+
+        Ok(WarcToArrowReader {
+            stream,
+            schema,
+            columns,
+            options: ExtractOptions {
+                max_decoded_payload_bytes: self.max_decoded_payload_bytes,
+                spill_path,
+                source_filename: self.source_filename,
+                digest_algorithm: self.digest_algorithm,
+                digest_encoding: self.digest_encoding,
+            },
+            batch_limits: BatchLimits {
+                records: self.batch_size,
+                bytes: self.max_batch_bytes,
+            },
+            error_policy: self.error_policy,
+        })
     }
 }
 
-/// A wrapper around a [`WarcReader`](warc::WarcReader) which provides a
-/// translation from a WARC source to an Arrow representation. The Arrow
-/// representation can then be used for different tasks, including persistence
-/// via a format such as Parquet.
+/// A reader which provides a translation from a WARC source to an Arrow
+/// representation. The Arrow representation can then be used for different
+/// tasks, including persistence via a format such as Parquet.
 ///
 /// # Example
 ///
@@ -98,7 +1111,8 @@ impl<R: BufRead> WarcToArrowReaderBuilder<R> {
 /// let input = BufReader::new(Cursor::new(warc_content));
 /// let mut reader = WarcToArrowReader::builder(input)
 ///     .with_batch_size(1024)
-///     .build();
+///     .build()
+///     .unwrap();
 /// let mut iter_reader = reader.iter_reader();
 ///
 /// let record_batch = iter_reader.next().unwrap().unwrap();
@@ -115,8 +1129,11 @@ impl<R: BufRead> WarcToArrowReaderBuilder<R> {
 /// ```
 pub struct WarcToArrowReader<R: BufRead> {
     schema: SchemaRef,
-    reader: WarcReader<R>,
-    batch_size: usize,
+    columns: Vec<ColumnSource>,
+    options: ExtractOptions,
+    stream: RecordStream<R>,
+    batch_limits: BatchLimits,
+    error_policy: ErrorPolicy,
 }
 
 impl<R: BufRead> WarcToArrowReader<R> {
@@ -126,50 +1143,212 @@ impl<R: BufRead> WarcToArrowReader<R> {
         WarcToArrowReaderBuilder::new(reader)
     }
 
+    /// Returns the schema of the record batches produced by the reader.
+    ///
+    /// This may differ from the schema given to the builder, e.g. under
+    /// [`ErrorPolicy::NullOut`] every field is nullable.
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    /// Returns the position in the WARC and the offset in the input at which
+    /// the reader's stream ended, once it has.
+    pub(crate) fn stream_end(&self) -> Option<(u64, u64)> {
+        self.stream.end()
+    }
+
     /// Returns an interface which can be used to iterate through record
     /// batches.
     pub fn iter_reader(&mut self) -> IterReader<'_, R> {
-        IterReader::new(self.reader.stream_records(), &self.schema, self.batch_size)
+        IterReader::new(
+            &mut self.stream,
+            &self.schema,
+            &self.columns,
+            &self.options,
+            self.batch_limits,
+            &mut self.error_policy,
+        )
     }
 }
 
-/// An iterator type for the underlying data. This consumes the streaming API of
-/// the [`WarcReader`], producing record batches of up to `batch_size`.
-pub struct IterReader<'r, R> {
+/// The bounds on the size of each record batch.
+#[derive(Clone, Copy)]
+pub(crate) struct BatchLimits {
+    /// The most records a batch may hold.
+    records: usize,
+    /// The most bytes of record headers and blocks a batch may hold, if
+    /// bounded. A batch always holds at least one record.
+    bytes: Option<usize>,
+}
+
+/// An iterator type for the underlying data. This consumes the WARC source
+/// record by record, producing record batches of up to `batch_size` records,
+/// or up to `max_batch_bytes` bytes if set.
+///
+/// Records dropped or altered according to the reader's [`ErrorPolicy`] are
+/// tallied and may be inspected once iteration is complete.
+pub struct IterReader<'r, R: BufRead> {
     schema: &'r SchemaRef,
-    stream_iter: StreamingIter<'r, R>,
-    batch_size: usize,
+    columns: &'r [ColumnSource],
+    options: &'r ExtractOptions,
+    stream: &'r mut RecordStream<R>,
+    batch_limits: BatchLimits,
+    error_policy: &'r mut ErrorPolicy,
     stream_ended: bool,
+    /// An error to be returned once the rows read ahead of it are.
+    pending_error: Option<WarcParquetError>,
+    records_skipped: u64,
+    records_quarantined: u64,
+    fields_nulled: u64,
+    digest_mismatches: u64,
 }
 
 impl<'r, R: BufRead> IterReader<'r, R> {
     pub(crate) fn new(
-        stream_iter: StreamingIter<'r, R>,
+        stream: &'r mut RecordStream<R>,
         schema: &'r SchemaRef,
-        batch_size: usize,
+        columns: &'r [ColumnSource],
+        options: &'r ExtractOptions,
+        batch_limits: BatchLimits,
+        error_policy: &'r mut ErrorPolicy,
     ) -> IterReader<'r, R> {
         Self {
             schema,
-            stream_iter,
-            batch_size,
+            columns,
+            options,
+            stream,
+            batch_limits,
+            error_policy,
             stream_ended: false,
+            pending_error: None,
+            records_skipped: 0,
+            records_quarantined: 0,
+            fields_nulled: 0,
+            digest_mismatches: 0,
         }
     }
-}
 
-impl<R: BufRead> Iterator for IterReader<'_, R> {
-    type Item = ReaderResult<RecordBatch>;
+    /// Returns the number of records dropped under [`ErrorPolicy::Skip`], as
+    /// well as unusable records dropped under [`ErrorPolicy::NullOut`].
+    pub fn records_skipped(&self) -> u64 {
+        self.records_skipped
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut records = Vec::with_capacity(self.batch_size);
-        while records.len() < self.batch_size && !self.stream_ended {
-            match self.stream_iter.next_item() {
-                Some(Ok(record)) => {
-                    records.push(record.into_buffered().expect("Failed to buffer record."));
-                }
+    /// Returns the number of records written to the quarantine sink under
+    /// [`ErrorPolicy::Quarantine`].
+    pub fn records_quarantined(&self) -> u64 {
+        self.records_quarantined
+    }
+
+    /// Returns the number of fields set to null under
+    /// [`ErrorPolicy::NullOut`].
+    pub fn fields_nulled(&self) -> u64 {
+        self.fields_nulled
+    }
+
+    /// Returns the number of records emitted whose block or payload digest
+    /// does not match, as reported by the
+    /// [`BlockDigestValid`](WarcColumn::BlockDigestValid) and
+    /// [`PayloadDigestValid`](WarcColumn::PayloadDigestValid) columns. Always
+    /// zero unless the schema includes either.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::io::{BufReader, Cursor};
+    ///
+    /// use arrow::array::BooleanArray;
+    /// use warc_parquet::{WarcColumn, WarcToArrowReader};
+    ///
+    /// # fn main() {
+    /// let warc_content = b"\
+    ///     WARC/1.0\r\n\
+    ///     WARC-Type: resource\r\n\
+    ///     Content-Length: 13\r\n\
+    ///     WARC-Record-Id: <urn:test:digest:record-0>\r\n\
+    ///     WARC-Date: 2020-07-08T02:52:55Z\r\n\
+    ///     WARC-Block-Digest: sha1:SQ5HALIG6NCZTLXB7DNI56PXFFQDDVUZ\r\n\
+    ///     \r\n\
+    ///     Hello, world!\r\n\
+    ///     \r\n\
+    ///     WARC/1.0\r\n\
+    ///     WARC-Type: resource\r\n\
+    ///     Content-Length: 13\r\n\
+    ///     WARC-Record-Id: <urn:test:digest:record-1>\r\n\
+    ///     WARC-Date: 2020-07-08T02:52:55Z\r\n\
+    ///     WARC-Block-Digest: sha1:SQ5HALIG6NCZTLXB7DNI56PXFFQDDVUZ\r\n\
+    ///     \r\n\
+    ///     Hello, World!\r\n\
+    ///     \r\n\
+    /// ";
+    ///
+    /// let input = BufReader::new(Cursor::new(warc_content));
+    /// let mut reader = WarcToArrowReader::builder(input)
+    ///     .with_columns(&[WarcColumn::Id, WarcColumn::BlockDigestValid])
+    ///     .build()
+    ///     .unwrap();
+    /// let mut iter_reader = reader.iter_reader();
+    /// let record_batch = iter_reader.next().unwrap().unwrap();
+    ///
+    /// let block_digest_valid = record_batch
+    ///     .column_by_name("block_digest_valid")
+    ///     .unwrap()
+    ///     .as_any()
+    ///     .downcast_ref::<BooleanArray>()
+    ///     .unwrap();
+    /// assert!(block_digest_valid.value(0));
+    /// assert!(!block_digest_valid.value(1));
+    /// assert_eq!(iter_reader.digest_mismatches(), 1);
+    /// # }
+    /// ```
+    pub fn digest_mismatches(&self) -> u64 {
+        self.digest_mismatches
+    }
+
+    /// Applies the error policy to a record which cannot be translated.
+    fn reject(&mut self, record: &RawRecord, err: WarcParquetError) -> ReaderResult<()> {
+        match self.error_policy {
+            ErrorPolicy::Fail => {
+                self.stream_ended = true;
+                return Err(err);
+            }
+            ErrorPolicy::Skip | ErrorPolicy::NullOut => self.records_skipped += 1,
+            ErrorPolicy::Quarantine(sink) => {
+                record.write_raw(sink)?;
+                self.records_quarantined += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_records(&mut self) -> ReaderResult<Vec<RawRecord>> {
+        let mut records = Vec::with_capacity(self.batch_limits.records);
+        let mut batch_bytes = 0;
+        while records.len() < self.batch_limits.records
+            && self
+                .batch_limits
+                .bytes
+                .is_none_or(|max_batch_bytes| batch_bytes < max_batch_bytes)
+            && !self.stream_ended
+        {
+            match self.stream.next_record() {
+                Some(Ok(mut record)) => match record.defect.take() {
+                    Some(defect) => {
+                        if let Err(err) = self.reject(&record, defect) {
+                            return self.defer_error(records, err);
+                        }
+                    }
+                    None => {
+                        batch_bytes += record.header_block.len() + record.body.len();
+                        records.push(record);
+                    }
+                },
 
                 Some(Err(err)) => {
-                    return Some(Err(err.into()));
+                    // The input can no longer be read, so there is nothing more
+                    // to be done.
+                    self.stream_ended = true;
+                    return self.defer_error(records, err);
                 }
 
                 None => {
@@ -179,251 +1358,137 @@ impl<R: BufRead> Iterator for IterReader<'_, R> {
             }
         }
 
-        if !records.is_empty() {
-            Some(build_record_batch(self.schema, &records))
-        } else {
-            None
-        }
+        Ok(records)
     }
-}
-
-fn build_record_batch(
-    schema: &SchemaRef,
-    records: &[Record<BufferedBody>],
-) -> ReaderResult<RecordBatch> {
-    let mut columns = Vec::with_capacity(records.len());
-
-    for field in schema.fields() {
-        let field_name = field.name();
-        let field_array: ArrayRef = match field_name.as_str() {
-            "id" => {
-                let id_values: Vec<_> = records
-                    .iter()
-                    .map(|record| {
-                        record
-                            .header(WarcHeader::RecordID)
-                            .map(|h| h.to_string())
-                            .expect("WARC-Record-ID header is mandatory.")
-                    })
-                    .collect();
-                Arc::new(StringArray::from(id_values))
-            }
-
-            "content_length" => {
-                let content_length_values: Vec<_> = records
-                    .iter()
-                    .map(|record| {
-                        record
-                            .header(WarcHeader::ContentLength)
-                            .map(|h| h.to_string().parse::<u32>().unwrap())
-                            .expect("Content-Length header is mandatory.")
-                    })
-                    .collect();
-                Arc::new(UInt32Array::from(content_length_values))
-            }
-
-            "date" => {
-                let date_values: Vec<_> = records
-                    .iter()
-                    .map(|record| {
-                        // "%Y-%m-%dT%H:%M:%SZ"
-                        record
-                            .header(WarcHeader::Date)
-                            .map(|h| {
-                                OffsetDateTime::parse(&h, &Iso8601::DEFAULT)
-                                    .unwrap()
-                                    .millisecond() as i64
-                            })
-                            .expect("WARC-Date header is mandatory.")
-                    })
-                    .collect();
-                Arc::new(TimestampMillisecondArray::from(date_values))
-            }
-
-            "type" => {
-                let type_values: Vec<_> = records
-                    .iter()
-                    .map(|record| {
-                        record
-                            .header(WarcHeader::WarcType)
-                            .map(|h| h.to_string())
-                            .expect("WARC-Type header is mandatory.")
-                    })
-                    .collect();
-                Arc::new(StringArray::from(type_values))
-            }
 
-            "content_type" => {
-                let content_type_values: Vec<_> = records
-                    .iter()
-                    .map(|record| {
-                        record
-                            .header(WarcHeader::ContentType)
-                            .map(|h| h.to_string())
-                    })
-                    .collect();
-                Arc::new(StringArray::from(content_type_values))
-            }
-
-            "concurrent_to" => {
-                let concurrent_to_values: Vec<_> = records
-                    .iter()
-                    .map(|record| {
-                        record
-                            .header(WarcHeader::ConcurrentTo)
-                            .map(|h| h.to_string())
-                    })
-                    .collect();
-                Arc::new(StringArray::from(concurrent_to_values))
-            }
-
-            "block_digest" => {
-                let block_digest_values: Vec<_> = records
-                    .iter()
-                    .map(|record| {
-                        record
-                            .header(WarcHeader::BlockDigest)
-                            .map(|h| h.to_string())
-                    })
-                    .collect();
-                Arc::new(StringArray::from(block_digest_values))
-            }
-
-            "payload_digest" => {
-                let payload_digest_values: Vec<_> = records
-                    .iter()
-                    .map(|record| {
-                        record
-                            .header(WarcHeader::PayloadDigest)
-                            .map(|h| h.to_string())
-                    })
-                    .collect();
-                Arc::new(StringArray::from(payload_digest_values))
-            }
-
-            "ip_address" => {
-                let ip_address_values: Vec<_> = records
-                    .iter()
-                    .map(|record| record.header(WarcHeader::IPAddress).map(|h| h.to_string()))
-                    .collect();
-                Arc::new(StringArray::from(ip_address_values))
-            }
-
-            "refers_to" => {
-                let refers_to_values: Vec<_> = records
-                    .iter()
-                    .map(|record| record.header(WarcHeader::RefersTo).map(|h| h.to_string()))
-                    .collect();
-                Arc::new(StringArray::from(refers_to_values))
-            }
-
-            "target_uri" => {
-                let target_uri_values: Vec<_> = records
-                    .iter()
-                    .map(|record| record.header(WarcHeader::TargetURI).map(|h| h.to_string()))
-                    .collect();
-                Arc::new(StringArray::from(target_uri_values))
-            }
+    /// Returns the records read ahead of an error, leaving the error to be
+    /// returned by the next read, or the error itself if there are none.
+    fn defer_error(
+        &mut self,
+        records: Vec<RawRecord>,
+        err: WarcParquetError,
+    ) -> ReaderResult<Vec<RawRecord>> {
+        if records.is_empty() {
+            return Err(err);
+        }
+        self.pending_error = Some(err);
+        Ok(records)
+    }
 
-            "truncated" => {
-                let truncated_values: Vec<_> = records
-                    .iter()
-                    .map(|record| record.header(WarcHeader::Truncated).map(|h| h.to_string()))
-                    .collect();
-                Arc::new(StringArray::from(truncated_values))
+    fn read_batch(&mut self) -> ReaderResult<Option<RecordBatch>> {
+        loop {
+            if let Some(err) = self.pending_error.take() {
+                return Err(err);
             }
 
-            "warc_info_id" => {
-                let warc_info_id_values: Vec<_> = records
-                    .iter()
-                    .map(|record| record.header(WarcHeader::WarcInfoID).map(|h| h.to_string()))
-                    .collect();
-                Arc::new(StringArray::from(warc_info_id_values))
+            let records = self.read_records()?;
+            if records.is_empty() {
+                if let ErrorPolicy::Quarantine(sink) = self.error_policy {
+                    sink.flush()?;
+                }
+                return Ok(None);
             }
 
-            "filename" => {
-                let filename_values: Vec<_> = records
-                    .iter()
-                    .map(|record| record.header(WarcHeader::Filename).map(|h| h.to_string()))
-                    .collect();
-                Arc::new(StringArray::from(filename_values))
-            }
+            // Rows may only reference blocks which are in the spill file.
+            self.stream.flush_spill()?;
 
-            "profile" => {
-                let profile_values: Vec<_> = records
-                    .iter()
-                    .map(|record| record.header(WarcHeader::Profile).map(|h| h.to_string()))
-                    .collect();
-                Arc::new(StringArray::from(profile_values))
-            }
+            let mut errors = Vec::new();
+            let mut columns = build_columns(
+                self.schema,
+                self.columns,
+                &records,
+                self.options,
+                &mut errors,
+            )?;
 
-            "identified_payload_type" => {
-                let identified_payload_type_values: Vec<_> = records
-                    .iter()
-                    .map(|record| {
-                        record
-                            .header(WarcHeader::IdentifiedPayloadType)
-                            .map(|h| h.to_string())
-                    })
-                    .collect();
-
-                Arc::new(StringArray::from(identified_payload_type_values))
-            }
+            if !errors.is_empty() {
+                if let ErrorPolicy::NullOut = self.error_policy {
+                    self.fields_nulled += errors.len() as u64;
+                } else if let ErrorPolicy::Fail = self.error_policy {
+                    // The rows preceding the earliest malformed record are
+                    // emitted before its error is returned, in place of any
+                    // later error the records were read ahead of.
+                    let (row, err) = errors
+                        .into_iter()
+                        .min_by_key(|(row, _)| *row)
+                        .expect("errors is not empty");
+                    self.stream_ended = true;
+                    self.pending_error = Some(err);
+                    columns = columns.iter().map(|column| column.slice(0, row)).collect();
+                } else {
+                    // Report the earliest malformed record first.
+                    errors.sort_by_key(|(row, _)| *row);
 
-            "segment_number" => {
-                let segment_number_values: Vec<_> = records
-                    .iter()
-                    .map(|record| {
-                        record.header(WarcHeader::SegmentNumber).map(|h| {
-                            h.to_string()
-                                .parse::<u32>()
-                                .expect("Malformed segment number.")
-                        })
-                    })
-                    .collect();
-
-                Arc::new(UInt32Array::from(segment_number_values))
-            }
+                    let mut keep = vec![true; records.len()];
+                    for (row, err) in errors {
+                        if keep[row] {
+                            keep[row] = false;
+                            self.reject(&records[row], err)?;
+                        }
+                    }
 
-            "segment_origin_id" => {
-                let segment_origin_id_values: Vec<_> = records
-                    .iter()
-                    .map(|record| {
-                        record
-                            .header(WarcHeader::SegmentOriginID)
-                            .map(|h| h.to_string())
-                    })
-                    .collect();
-
-                Arc::new(StringArray::from(segment_origin_id_values))
+                    let predicate = BooleanArray::from(keep);
+                    columns = columns
+                        .iter()
+                        .map(|column| filter(column, &predicate))
+                        .collect::<Result<_, _>>()?;
+                }
             }
 
-            "segment_total_length" => {
-                let segment_total_length_values: Vec<_> = records
-                    .iter()
-                    .map(|record| {
-                        record.header(WarcHeader::SegmentTotalLength).map(|h| {
-                            h.to_string()
-                                .parse::<u32>()
-                                .expect("Malformed segment total length.")
-                        })
-                    })
-                    .collect();
-
-                Arc::new(UInt32Array::from(segment_total_length_values))
+            let record_batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+            self.digest_mismatches += count_digest_mismatches(self.columns, &record_batch);
+            if record_batch.num_rows() > 0 {
+                return Ok(Some(record_batch));
             }
+        }
+    }
+}
 
-            "body" => {
-                let body_values: Vec<_> = records.iter().map(|record| record.body()).collect();
+impl<R: BufRead> Iterator for IterReader<'_, R> {
+    type Item = ReaderResult<RecordBatch>;
 
-                Arc::new(BinaryArray::from(body_values))
-            }
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_batch().transpose()
+    }
+}
 
-            _ => unimplemented!(),
+/// Counts the rows of the batch whose block or payload digest does not match.
+fn count_digest_mismatches(columns: &[ColumnSource], record_batch: &RecordBatch) -> u64 {
+    let mut mismatched = vec![false; record_batch.num_rows()];
+    for (column, array) in columns.iter().zip(record_batch.columns()) {
+        let ColumnSource::Warc(WarcColumn::BlockDigestValid | WarcColumn::PayloadDigestValid) =
+            column
+        else {
+            continue;
         };
-
-        columns.push(field_array);
+        let Some(digest_valid) = array.as_any().downcast_ref::<BooleanArray>() else {
+            continue;
+        };
+        for (row, is_valid) in digest_valid.iter().enumerate() {
+            mismatched[row] |= is_valid == Some(false);
+        }
     }
+    mismatched
+        .into_iter()
+        .filter(|&is_mismatched| is_mismatched)
+        .count() as u64
+}
 
-    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+/// Builds a column for each field of the schema.
+///
+/// Values which cannot be extracted from a record are left null, with the
+/// error and the row it occurred in pushed onto `errors`.
+fn build_columns(
+    schema: &SchemaRef,
+    columns: &[ColumnSource],
+    records: &[RawRecord],
+    options: &ExtractOptions,
+    errors: &mut Vec<(usize, WarcParquetError)>,
+) -> ReaderResult<Vec<ArrayRef>> {
+    schema
+        .fields()
+        .iter()
+        .zip(columns)
+        .map(|(field, column)| column.build_array(field, records, options, errors))
+        .collect()
 }
@@ -0,0 +1,520 @@
+use std::{
+    cell::OnceCell,
+    io::{self, BufRead, Read, Write},
+    str::FromStr,
+};
+
+use crate::{error::WarcParquetError, http::HttpMessage, source::WarcSource, spill::BodySpill};
+
+type RecordResult<T> = Result<T, WarcParquetError>;
+
+/// The most bytes of a record preceding its block that are read into memory,
+/// covering both its header block and, for a malformed record, the bytes
+/// skipped over in search of the next record. Records which exceed it are
+/// malformed, and the input past it is read as further malformed records.
+const MAX_HEADER_BLOCK_BYTES: usize = 1024 * 1024;
+
+/// A single WARC record as read from the input, prior to any interpretation of
+/// its header values.
+pub(crate) struct RawRecord {
+    /// The byte offset of the record in the input.
+    pub(crate) offset: u64,
+    /// The byte offset of the record in the input as given, i.e. of the gzip
+    /// member holding it for compressed input.
+    pub(crate) source_offset: u64,
+    /// The number of bytes of the input as given the record spans, including
+    /// the line endings following it.
+    pub(crate) source_length: u64,
+    /// The version from the `WARC/` line, e.g. `1.0`.
+    pub(crate) version: String,
+    /// The named fields of the record, in the order they appear.
+    pub(crate) headers: Vec<(String, Vec<u8>)>,
+    /// The bytes of the record preceding its block, exactly as read.
+    pub(crate) header_block: Vec<u8>,
+    /// The record block, or as much of it as the stream keeps: blocks are
+    /// truncated or spilled per the stream's [`BlockHandling`].
+    pub(crate) body: Vec<u8>,
+    /// The number of bytes of the record block read from the input, which
+    /// exceeds the length of `body` when the block was truncated or spilled.
+    pub(crate) block_length: u64,
+    /// Set when the record is not well-formed. Such records are still returned
+    /// so that their raw bytes can be accounted for.
+    pub(crate) defect: Option<WarcParquetError>,
+    /// The offset of the block in the reader's spill file, if it was written
+    /// there rather than stored inline.
+    pub(crate) spilled_at: Option<u64>,
+    /// The HTTP message held in the block, parsed on first use.
+    http: OnceCell<Option<Result<HttpMessage, &'static str>>>,
+    /// The HTTP body with its codings removed, decoded on first use.
+    decoded_payload: OnceCell<io::Result<Option<Vec<u8>>>>,
+}
+
+impl RawRecord {
+    /// Returns the raw value of the first header with the given name. Names are
+    /// compared case-insensitively.
+    pub(crate) fn header(&self, name: &str) -> Option<&[u8]> {
+        self.headers
+            .iter()
+            .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_slice())
+    }
+
+    /// Returns the value of the named header as a string.
+    pub(crate) fn header_str(&self, name: &str) -> RecordResult<Option<&str>> {
+        self.header(name)
+            .map(|value| std::str::from_utf8(value).map_err(|_| self.malformed_header(name, value)))
+            .transpose()
+    }
+
+    /// Returns the value of a header the specification mandates.
+    pub(crate) fn required_header_str(&self, name: &str) -> RecordResult<&str> {
+        self.header_str(name)?
+            .ok_or_else(|| WarcParquetError::MissingHeader {
+                header: name.to_string(),
+                offset: self.offset,
+            })
+    }
+
+    /// Returns the value of the named header parsed as `T`.
+    pub(crate) fn parse_header<T: FromStr>(&self, name: &str) -> RecordResult<Option<T>> {
+        self.header_str(name)?
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| self.malformed_header(name, value.as_bytes()))
+            })
+            .transpose()
+    }
+
+    /// Returns the value of a header the specification mandates parsed as
+    /// `T`.
+    pub(crate) fn parse_required_header<T: FromStr>(&self, name: &str) -> RecordResult<T> {
+        let value = self.required_header_str(name)?;
+        value
+            .parse()
+            .map_err(|_| self.malformed_header(name, value.as_bytes()))
+    }
+
+    pub(crate) fn malformed_header(&self, name: &str, value: &[u8]) -> WarcParquetError {
+        WarcParquetError::MalformedHeader {
+            header: name.to_string(),
+            value: String::from_utf8_lossy(value).into_owned(),
+            offset: self.offset,
+        }
+    }
+
+    /// Returns the `WARC-Target-URI` header.
+    ///
+    /// WARC 1.0 delimits the URI with angle brackets whereas WARC 1.1 does
+    /// not. Removing them makes URIs from either version comparable.
+    pub(crate) fn target_uri(&self) -> RecordResult<Option<&str>> {
+        let target_uri = self.header_str("WARC-Target-URI")?;
+        Ok(target_uri.map(|uri| {
+            uri.strip_prefix('<')
+                .and_then(|uri| uri.strip_suffix('>'))
+                .unwrap_or(uri)
+        }))
+    }
+
+    /// Returns whether `body` holds the whole of the record block, rather
+    /// than a truncated or spilled block.
+    pub(crate) fn is_block_whole(&self) -> bool {
+        self.body.len() as u64 == self.block_length
+    }
+
+    /// Returns the HTTP message the record block holds, if the record's
+    /// `Content-Type` declares one.
+    ///
+    /// A block which is not held whole may not hold the whole of the HTTP head
+    /// either, so for such blocks a head which cannot be parsed is taken to be
+    /// absent rather than malformed.
+    pub(crate) fn http(&self) -> RecordResult<Option<&HttpMessage>> {
+        let http = self.http.get_or_init(|| {
+            let content_type = self.header("Content-Type").unwrap_or_default();
+            let is_http = content_type
+                .get(..16)
+                .is_some_and(|media_type| media_type.eq_ignore_ascii_case(b"application/http"));
+            let message = is_http.then(|| HttpMessage::parse(&self.body))?;
+            match message {
+                Err(_) if !self.is_block_whole() => None,
+                message => Some(message),
+            }
+        });
+
+        match http {
+            Some(Ok(message)) => Ok(Some(message)),
+            Some(Err(reason)) => Err(WarcParquetError::MalformedRecord {
+                reason,
+                offset: self.offset,
+            }),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the record payload: the body of the HTTP message for records
+    /// holding one, otherwise the record block.
+    pub(crate) fn payload(&self) -> RecordResult<&[u8]> {
+        let payload_offset = self.http()?.map_or(0, |message| message.payload_offset);
+        Ok(&self.body[payload_offset..])
+    }
+
+    /// Returns the record payload with any HTTP transfer and content codings
+    /// removed, or `None` if they could not be removed within `limit` bytes.
+    pub(crate) fn decoded_payload(&self, limit: usize) -> RecordResult<Option<&[u8]>> {
+        let payload = self.payload()?;
+        let Some(message) = self.http()? else {
+            return Ok(Some(payload));
+        };
+
+        let decoded_payload = self
+            .decoded_payload
+            .get_or_init(|| message.decode_body(payload, limit));
+        Ok(match decoded_payload {
+            Ok(Some(decoded_payload)) => Some(decoded_payload),
+            Ok(None) => Some(payload),
+            Err(_) => None,
+        })
+    }
+
+    /// Writes the record to the sink as it was read from the input, with as
+    /// much of its block as `body` holds.
+    pub(crate) fn write_raw<W: Write + ?Sized>(&self, sink: &mut W) -> io::Result<()> {
+        sink.write_all(&self.header_block)?;
+        sink.write_all(&self.body)?;
+        sink.write_all(b"\r\n\r\n")
+    }
+
+    fn set_defect(&mut self, defect: WarcParquetError) {
+        // The first defect is the most telling, later ones tend to follow from it.
+        self.defect.get_or_insert(defect);
+    }
+
+    fn set_malformed(&mut self, reason: &'static str) {
+        self.set_defect(WarcParquetError::MalformedRecord {
+            reason,
+            offset: self.offset,
+        });
+    }
+}
+
+/// What a [`RecordStream`] does with the blocks of the records it reads.
+pub(crate) enum BlockHandling {
+    /// Read past blocks, returning records with empty bodies.
+    Skip,
+
+    /// Keep blocks, truncated to the given number of bytes if bounded. The
+    /// rest of a truncated block is read past.
+    Keep(Option<usize>),
+
+    /// Keep blocks up to the spill's threshold, streaming larger ones into the
+    /// spill file as they are read.
+    Spill(BodySpill),
+}
+
+/// Reads [`RawRecord`]s one after another from a WARC source, keeping track of
+/// the byte offset of each.
+///
+/// Malformed records do not end the stream: the stream resynchronizes on the
+/// next `WARC/` version line and the skipped bytes are returned as a record
+/// carrying a defect.
+pub(crate) struct RecordStream<R: BufRead> {
+    reader: WarcSource<R>,
+    position: u64,
+    pending_line: Option<(u64, Vec<u8>)>,
+    block_handling: BlockHandling,
+    source_limit: Option<u64>,
+    end: Option<(u64, u64)>,
+}
+
+impl<R: BufRead> RecordStream<R> {
+    /// Creates a stream over the source, handling record blocks as given.
+    pub(crate) fn new(reader: WarcSource<R>, block_handling: BlockHandling) -> Self {
+        Self {
+            reader,
+            position: 0,
+            pending_line: None,
+            block_handling,
+            source_limit: None,
+            end: None,
+        }
+    }
+
+    /// Ends the stream before the first record beginning at or past the given
+    /// offset in the input.
+    pub(crate) fn with_source_limit(mut self, source_limit: u64) -> Self {
+        self.source_limit = Some(source_limit);
+        self
+    }
+
+    /// Returns the position in the WARC and the offset in the input at which
+    /// the stream ended, once it has.
+    pub(crate) fn end(&self) -> Option<(u64, u64)> {
+        self.end
+    }
+
+    /// Returns the path of the file blocks are spilled to, if they are.
+    pub(crate) fn spill_path(&self) -> Option<&str> {
+        match &self.block_handling {
+            BlockHandling::Spill(spill) => Some(spill.path()),
+            _ => None,
+        }
+    }
+
+    /// Flushes the blocks spilled so far, so that rows may reference them.
+    pub(crate) fn flush_spill(&mut self) -> io::Result<()> {
+        match &mut self.block_handling {
+            BlockHandling::Spill(spill) => spill.flush(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reads the next record from the stream.
+    ///
+    /// Returns `None` once the stream is exhausted. Errors are only returned
+    /// when the input itself can no longer be read.
+    pub(crate) fn next_record(&mut self) -> Option<RecordResult<RawRecord>> {
+        self.read_record().transpose()
+    }
+
+    fn read_record(&mut self) -> RecordResult<Option<RawRecord>> {
+        let Some(mut record) = self.read_raw_record()? else {
+            return Ok(None);
+        };
+
+        // The line endings separating records are counted towards the record
+        // preceding them, as is conventional for WARC indexes.
+        let end = match &self.pending_line {
+            Some((offset, _)) => *offset,
+            None => {
+                self.skip_line_endings()?;
+                self.position
+            }
+        };
+        record.source_length = self.reader.source_offset(end) - record.source_offset;
+
+        Ok(Some(record))
+    }
+
+    fn read_raw_record(&mut self) -> RecordResult<Option<RawRecord>> {
+        if self.end.is_some() {
+            return Ok(None);
+        }
+
+        let (offset, first_line) = match self.pending_line.take() {
+            Some(pending_line) => pending_line,
+            None => {
+                // Records are separated by two CRLFs, however stray line endings
+                // are common enough in the wild that any number of them are
+                // tolerated.
+                self.skip_line_endings()?;
+
+                let offset = self.position;
+                let mut line = Vec::new();
+                if self.read_line(&mut line, MAX_HEADER_BLOCK_BYTES)? == 0 {
+                    self.end = Some((offset, self.reader.source_offset(offset)));
+                    return Ok(None);
+                }
+                (offset, line)
+            }
+        };
+
+        let source_offset = self.reader.source_offset(offset);
+        if self
+            .source_limit
+            .is_some_and(|source_limit| source_offset >= source_limit)
+        {
+            self.end = Some((offset, source_offset));
+            return Ok(None);
+        }
+
+        self.reader.forget_before(offset);
+        let mut record = RawRecord {
+            offset,
+            source_offset,
+            source_length: 0,
+            version: String::new(),
+            headers: Vec::new(),
+            header_block: first_line,
+            body: Vec::new(),
+            block_length: 0,
+            defect: None,
+            spilled_at: None,
+            http: OnceCell::new(),
+            decoded_payload: OnceCell::new(),
+        };
+
+        match trim_line_ending(&record.header_block).strip_prefix(b"WARC/") {
+            Some(version) => record.version = String::from_utf8_lossy(version).into_owned(),
+            None => {
+                record.set_malformed("missing WARC version line");
+                self.resync(&mut record)?;
+                return Ok(Some(record));
+            }
+        }
+
+        loop {
+            let line_start = record.header_block.len();
+            let limit = MAX_HEADER_BLOCK_BYTES.saturating_sub(line_start);
+            if self.read_line(&mut record.header_block, limit)? == 0 {
+                record.set_malformed(if limit == 0 {
+                    "header block too large"
+                } else {
+                    "unexpected end of header block"
+                });
+                return Ok(Some(record));
+            }
+            if !record.header_block.ends_with(b"\n")
+                && record.header_block.len() >= MAX_HEADER_BLOCK_BYTES
+            {
+                record.set_malformed("header block too large");
+                return Ok(Some(record));
+            }
+
+            let line = trim_line_ending(&record.header_block[line_start..]);
+            if line.is_empty() {
+                break;
+            }
+
+            // Lines beginning with whitespace continue the previous header.
+            if line[0] == b' ' || line[0] == b'\t' {
+                if let Some((_, value)) = record.headers.last_mut() {
+                    value.push(b' ');
+                    value.extend_from_slice(line.trim_ascii());
+                } else {
+                    record.set_malformed("continuation line without a header");
+                }
+                continue;
+            }
+
+            match line.iter().position(|&b| b == b':') {
+                Some(colon) => {
+                    let name = String::from_utf8_lossy(line[..colon].trim_ascii()).into_owned();
+                    let value = line[colon + 1..].trim_ascii().to_vec();
+                    record.headers.push((name, value));
+                }
+                None => record.set_malformed("header line without a colon"),
+            }
+        }
+
+        let content_length = match record.parse_header::<u64>("Content-Length") {
+            Ok(Some(content_length)) => content_length,
+            Ok(None) => {
+                record.set_defect(WarcParquetError::MissingHeader {
+                    header: "Content-Length".to_string(),
+                    offset: record.offset,
+                });
+                self.resync(&mut record)?;
+                return Ok(Some(record));
+            }
+            Err(err) => {
+                record.set_defect(err);
+                self.resync(&mut record)?;
+                return Ok(Some(record));
+            }
+        };
+
+        let mut block = (&mut self.reader).take(content_length);
+        let bytes_read = match &mut self.block_handling {
+            BlockHandling::Skip => io::copy(&mut block, &mut io::sink())?,
+            BlockHandling::Keep(max_body_bytes) => {
+                let kept_bytes =
+                    max_body_bytes.map_or(u64::MAX, |max_body_bytes| max_body_bytes as u64);
+                let bytes_kept = (&mut block)
+                    .take(kept_bytes)
+                    .read_to_end(&mut record.body)?;
+                bytes_kept as u64 + io::copy(&mut block, &mut io::sink())?
+            }
+            BlockHandling::Spill(spill) if content_length > spill.threshold() as u64 => {
+                let (spilled_at, bytes_spilled) = spill.write_block(&mut block)?;
+                record.spilled_at = Some(spilled_at);
+                bytes_spilled
+            }
+            BlockHandling::Spill(_) => block.read_to_end(&mut record.body)? as u64,
+        };
+        record.block_length = bytes_read;
+        self.position += bytes_read;
+        if bytes_read < content_length {
+            record.set_malformed("unexpected end of record block");
+        }
+
+        Ok(Some(record))
+    }
+
+    /// Consumes lines until the next line which looks like the start of a
+    /// record, appending them to the given record's raw bytes.
+    ///
+    /// Once the record's raw bytes reach [`MAX_HEADER_BLOCK_BYTES`] the record
+    /// is marked malformed and returned, leaving the lines past it to be read
+    /// as another record.
+    fn resync(&mut self, record: &mut RawRecord) -> RecordResult<()> {
+        loop {
+            let limit = MAX_HEADER_BLOCK_BYTES.saturating_sub(record.header_block.len());
+            if limit == 0 {
+                record.set_malformed("header block too large");
+                return Ok(());
+            }
+
+            let offset = self.position;
+            let mut line = Vec::new();
+            if self.read_line(&mut line, limit)? == 0 {
+                return Ok(());
+            }
+
+            if line.starts_with(b"WARC/") {
+                self.pending_line = Some((offset, line));
+                return Ok(());
+            }
+
+            record.header_block.extend_from_slice(&line);
+        }
+    }
+
+    /// Appends the next line, including its line ending, to `buf`, stopping
+    /// short of the line ending once `limit` bytes have been appended.
+    fn read_line(&mut self, buf: &mut Vec<u8>, limit: usize) -> RecordResult<usize> {
+        let mut bytes_read = 0;
+        while bytes_read < limit {
+            let available = self.reader.fill_buf()?;
+            let available = &available[..available.len().min(limit - bytes_read)];
+            if available.is_empty() {
+                break;
+            }
+
+            let (line_len, line_ended) = match available.iter().position(|&b| b == b'\n') {
+                Some(newline) => (newline + 1, true),
+                None => (available.len(), false),
+            };
+            buf.extend_from_slice(&available[..line_len]);
+            self.reader.consume(line_len);
+            bytes_read += line_len;
+            if line_ended {
+                break;
+            }
+        }
+
+        self.position += bytes_read as u64;
+        Ok(bytes_read)
+    }
+
+    fn skip_line_endings(&mut self) -> RecordResult<()> {
+        loop {
+            let buf = self.reader.fill_buf()?;
+            let available = buf.len();
+            let line_endings = buf
+                .iter()
+                .take_while(|&&b| b == b'\r' || b == b'\n')
+                .count();
+            self.reader.consume(line_endings);
+            self.position += line_endings as u64;
+
+            if available == 0 || line_endings < available {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn trim_line_ending(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
@@ -3,6 +3,9 @@ use std::sync::Arc;
 use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
 use lazy_static::lazy_static;
 
+/// The timezone of WARC-Date values, which the specification requires be UTC.
+pub const UTC: &str = "UTC";
+
 lazy_static! {
     /// The WARC Format 1.0 schema.
     ///
@@ -12,10 +15,10 @@ lazy_static! {
         Arc::new(Schema::new(vec![
             // Mandatory fields.
             Field::new("id", DataType::Utf8, false),
-            Field::new("content_length", DataType::UInt32, false),
+            Field::new("content_length", DataType::UInt64, false),
             Field::new(
                 "date",
-                DataType::Timestamp(TimeUnit::Millisecond, None),
+                DataType::Timestamp(TimeUnit::Millisecond, Some(UTC.into())),
                 false,
             ),
             Field::new("type", DataType::Utf8, false),
@@ -35,7 +38,108 @@ lazy_static! {
             Field::new("identified_payload_type", DataType::Utf8, true),
             Field::new("segment_number", DataType::UInt32, true),
             Field::new("segment_origin_id", DataType::Utf8, true),
-            Field::new("segment_total_length", DataType::UInt32, true),
+            Field::new("segment_total_length", DataType::UInt64, true),
+            Field::new("body", DataType::Binary, true),
+        ]));
+
+    /// The WARC Format 1.1 schema.
+    ///
+    /// This is a superset of [`WARC_1_0_SCHEMA`](static@WARC_1_0_SCHEMA) and so
+    /// accommodates archives mixing records of either version. The `version`
+    /// field records the version each record declares. Dates are stored with
+    /// nanosecond precision, the finest the standard permits.
+    ///
+    /// This specification is drawn from the standard
+    /// [document](https://iipc.github.io/warc-specifications/specifications/warc-format/warc-1.1/).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::io::{BufReader, Cursor};
+    ///
+    /// use arrow::array::StringArray;
+    /// use warc_parquet::{WarcToArrowReader, WARC_1_1_SCHEMA};
+    ///
+    /// # fn main() {
+    /// let warc_content = b"\
+    ///     WARC/1.0\r\n\
+    ///     WARC-Type: response\r\n\
+    ///     Content-Length: 0\r\n\
+    ///     WARC-Record-Id: <urn:test:mixed:record-0>\r\n\
+    ///     WARC-Date: 2020-07-08T02:52:55Z\r\n\
+    ///     WARC-Target-URI: <http://example.com/>\r\n\
+    ///     \r\n\
+    ///     \r\n\
+    ///     \r\n\
+    ///     WARC/1.1\r\n\
+    ///     WARC-Type: response\r\n\
+    ///     Content-Length: 0\r\n\
+    ///     WARC-Record-Id: <urn:test:mixed:record-1>\r\n\
+    ///     WARC-Date: 2020-07-08T02:52:55.5Z\r\n\
+    ///     WARC-Target-URI: http://example.com/\r\n\
+    ///     \r\n\
+    ///     \r\n\
+    ///     \r\n\
+    /// ";
+    ///
+    /// let input = BufReader::new(Cursor::new(warc_content));
+    /// let mut reader = WarcToArrowReader::builder(input)
+    ///     .with_schema(WARC_1_1_SCHEMA.clone())
+    ///     .build()
+    ///     .unwrap();
+    /// let record_batch = reader.iter_reader().next().unwrap().unwrap();
+    ///
+    /// let column = |name| {
+    ///     record_batch
+    ///         .column_by_name(name)
+    ///         .unwrap()
+    ///         .as_any()
+    ///         .downcast_ref::<StringArray>()
+    ///         .unwrap()
+    ///         .clone()
+    /// };
+    /// assert_eq!(column("version"), StringArray::from(vec!["1.0", "1.1"]));
+    /// assert_eq!(
+    ///     column("target_uri"),
+    ///     StringArray::from(vec!["http://example.com/", "http://example.com/"])
+    /// );
+    /// # }
+    /// ```
+    pub static ref WARC_1_1_SCHEMA: SchemaRef =
+        Arc::new(Schema::new(vec![
+            // Mandatory fields.
+            Field::new("version", DataType::Utf8, false),
+            Field::new("id", DataType::Utf8, false),
+            Field::new("content_length", DataType::UInt64, false),
+            Field::new(
+                "date",
+                DataType::Timestamp(TimeUnit::Nanosecond, Some(UTC.into())),
+                false,
+            ),
+            Field::new("type", DataType::Utf8, false),
+
+            // Optional fields.
+            Field::new("content_type", DataType::Utf8, true),
+            Field::new("concurrent_to", DataType::Utf8, true),
+            Field::new("block_digest", DataType::Utf8, true),
+            Field::new("payload_digest", DataType::Utf8, true),
+            Field::new("ip_address", DataType::Utf8, true),
+            Field::new("refers_to", DataType::Utf8, true),
+            Field::new("refers_to_target_uri", DataType::Utf8, true),
+            Field::new(
+                "refers_to_date",
+                DataType::Timestamp(TimeUnit::Nanosecond, Some(UTC.into())),
+                true,
+            ),
+            Field::new("target_uri", DataType::Utf8, true),
+            Field::new("truncated", DataType::Utf8, true),
+            Field::new("warc_info_id", DataType::Utf8, true),
+            Field::new("filename", DataType::Utf8, true),
+            Field::new("profile", DataType::Utf8, true),
+            Field::new("identified_payload_type", DataType::Utf8, true),
+            Field::new("segment_number", DataType::UInt32, true),
+            Field::new("segment_origin_id", DataType::Utf8, true),
+            Field::new("segment_total_length", DataType::UInt64, true),
             Field::new("body", DataType::Binary, true),
         ]));
 }
@@ -0,0 +1,467 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+};
+
+use bzip2::bufread::BzDecoder;
+use libflate::gzip;
+use zstd::dict::DecoderDictionary;
+
+/// The compression applied to a WARC file as a whole.
+///
+/// Compressed WARC is conventionally compressed one record per gzip member,
+/// Zstandard frame or bzip2 stream, so that a record may be read by
+/// decompressing the member at its offset alone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WarcCompression {
+    /// Uncompressed WARC.
+    Uncompressed,
+
+    /// WARC compressed with gzip, e.g. `.warc.gz`.
+    Gzip,
+
+    /// WARC compressed with Zstandard, e.g. `.warc.zst`, optionally with a
+    /// dictionary held in a skippable frame preceding the records, as
+    /// [proposed](https://iipc.github.io/warc-specifications/specifications/warc-zstd/)
+    /// by the IIPC.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::io::{BufReader, Cursor, Write};
+    ///
+    /// use warc_parquet::{WarcCompression, WarcToArrowReader};
+    ///
+    /// # fn main() {
+    /// let record = |id: &str| {
+    ///     format!(
+    ///         "WARC/1.0\r\n\
+    ///          WARC-Type: resource\r\n\
+    ///          Content-Length: 13\r\n\
+    ///          WARC-Record-Id: <urn:test:zstd:{id}>\r\n\
+    ///          WARC-Date: 2020-07-08T02:52:55Z\r\n\
+    ///          \r\n\
+    ///          Hello, world!\r\n\
+    ///          \r\n"
+    ///     )
+    /// };
+    ///
+    /// // A raw-content dictionary, held in a skippable frame ahead of the
+    /// // records, each of which is compressed as a frame of its own.
+    /// let dictionary = record("dictionary").into_bytes();
+    /// let mut compressed = Vec::new();
+    /// compressed.extend(0x184D2A5Du32.to_le_bytes());
+    /// compressed.extend((dictionary.len() as u32).to_le_bytes());
+    /// compressed.extend(&dictionary);
+    /// for id in ["record-0", "record-1"] {
+    ///     let mut encoder =
+    ///         zstd::stream::write::Encoder::with_dictionary(Vec::new(), 3, &dictionary).unwrap();
+    ///     encoder.write_all(record(id).as_bytes()).unwrap();
+    ///     compressed.extend(encoder.finish().unwrap());
+    /// }
+    ///
+    /// assert_eq!(WarcCompression::from_magic(&compressed), WarcCompression::Zstd);
+    ///
+    /// let input = BufReader::new(Cursor::new(compressed));
+    /// let mut reader = WarcToArrowReader::builder(input).build().unwrap();
+    /// let record_batch = reader.iter_reader().next().unwrap().unwrap();
+    /// assert_eq!(record_batch.num_rows(), 2);
+    /// # }
+    /// ```
+    Zstd,
+
+    /// WARC compressed with bzip2.
+    Bzip2,
+}
+
+impl WarcCompression {
+    /// Identifies the compression of a file beginning with the given bytes
+    /// from its magic number. Bytes which match no known magic number are
+    /// taken to be uncompressed.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::io::{BufReader, Cursor, Write};
+    ///
+    /// use bzip2::{write::BzEncoder, Compression};
+    /// use warc_parquet::{WarcCompression, WarcToArrowReader};
+    ///
+    /// # fn main() {
+    /// let warc_content = b"\
+    ///     WARC/1.0\r\n\
+    ///     WARC-Type: resource\r\n\
+    ///     Content-Length: 0\r\n\
+    ///     WARC-Record-Id: <urn:test:from-magic:record-0>\r\n\
+    ///     WARC-Date: 2020-07-08T02:52:55Z\r\n\
+    ///     \r\n\
+    ///     \r\n\
+    ///     \r\n\
+    /// ";
+    ///
+    /// let zstd = zstd::stream::encode_all(warc_content.as_slice(), 3).unwrap();
+    /// let mut encoder = BzEncoder::new(Vec::new(), Compression::default());
+    /// encoder.write_all(warc_content).unwrap();
+    /// let bzip2 = encoder.finish().unwrap();
+    ///
+    /// assert_eq!(WarcCompression::from_magic(warc_content), WarcCompression::Uncompressed);
+    /// assert_eq!(WarcCompression::from_magic(&zstd), WarcCompression::Zstd);
+    /// assert_eq!(WarcCompression::from_magic(&bzip2), WarcCompression::Bzip2);
+    ///
+    /// // Readers detect the compression of their input in the same way.
+    /// for compressed in [zstd, bzip2] {
+    ///     let input = BufReader::new(Cursor::new(compressed));
+    ///     let mut reader = WarcToArrowReader::builder(input).build().unwrap();
+    ///     let record_batch = reader.iter_reader().next().unwrap().unwrap();
+    ///     assert_eq!(record_batch.num_rows(), 1);
+    /// }
+    /// # }
+    /// ```
+    pub fn from_magic(bytes: &[u8]) -> Self {
+        match bytes {
+            [0x1f, 0x8b, ..] => WarcCompression::Gzip,
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => WarcCompression::Zstd,
+            // Zstandard skippable frames, which may hold a dictionary.
+            [0x50..=0x5f, 0x2a, 0x4d, 0x18, ..] => WarcCompression::Zstd,
+            [b'B', b'Z', b'h', ..] => WarcCompression::Bzip2,
+            _ => WarcCompression::Uncompressed,
+        }
+    }
+
+    /// Identifies the compression of the reader's input from its magic number
+    /// without consuming any of it.
+    pub fn detect<R: BufRead>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self::from_magic(reader.fill_buf()?))
+    }
+}
+
+/// WARC read from possibly compressed input, decompressed as it is read.
+///
+/// Positions in the decompressed WARC are mapped back to the member holding
+/// them, so that records may be located in the compressed input.
+///
+/// # Example
+///
+/// ```rust
+/// use std::io::{BufReader, Cursor, Read, Write};
+///
+/// use libflate::gzip::Encoder;
+/// use warc_parquet::{WarcCompression, WarcSource};
+///
+/// # fn main() {
+/// let mut encoder = Encoder::new(Vec::new()).unwrap();
+/// encoder.write_all(b"WARC/1.0\r\n").unwrap();
+/// let compressed = encoder.finish().into_result().unwrap();
+///
+/// let mut source = WarcSource::detect(BufReader::new(Cursor::new(compressed))).unwrap();
+/// assert_eq!(source.compression(), WarcCompression::Gzip);
+///
+/// let mut warc = String::new();
+/// source.read_to_string(&mut warc).unwrap();
+/// assert_eq!(warc, "WARC/1.0\r\n");
+/// # }
+/// ```
+pub struct WarcSource<R: BufRead> {
+    compression: WarcCompression,
+    inner: SourceInner<R>,
+    /// The offset in the file of the start of the input.
+    source_start: u64,
+}
+
+enum SourceInner<R: BufRead> {
+    Uncompressed(R),
+    Compressed(Box<BufReader<Members<R>>>),
+}
+
+impl WarcSource<BufReader<File>> {
+    /// Opens the WARC file at the path, detecting its compression.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::detect(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: BufRead> WarcSource<R> {
+    /// Creates a source over input with the given compression.
+    pub fn new(reader: R, compression: WarcCompression) -> Self {
+        let inner = match compression {
+            WarcCompression::Uncompressed => SourceInner::Uncompressed(reader),
+            _ => {
+                SourceInner::Compressed(Box::new(BufReader::new(Members::new(reader, compression))))
+            }
+        };
+        Self {
+            compression,
+            inner,
+            source_start: 0,
+        }
+    }
+
+    /// Creates a source over input of any supported compression, detected
+    /// from its magic number.
+    pub fn detect(mut reader: R) -> io::Result<Self> {
+        let compression = WarcCompression::detect(&mut reader)?;
+        Ok(Self::new(reader, compression))
+    }
+
+    /// Returns the compression of the input.
+    pub fn compression(&self) -> WarcCompression {
+        self.compression
+    }
+
+    /// Sets the offset in the file at which the input begins, for input which
+    /// was positioned partway into a file. Offsets in the input are reported
+    /// relative to the start of the file.
+    pub(crate) fn with_source_start(mut self, source_start: u64) -> Self {
+        self.source_start = source_start;
+        self
+    }
+
+    /// Returns the offset in the input of the given position of the WARC. For
+    /// compressed input this is the offset of the member holding that
+    /// position, or the length of the input for the position where the WARC
+    /// ends.
+    pub(crate) fn source_offset(&self, position: u64) -> u64 {
+        self.source_start
+            + match &self.inner {
+                SourceInner::Uncompressed(_) => position,
+                SourceInner::Compressed(reader) => reader.get_ref().source_offset(position),
+            }
+    }
+
+    /// Discards what is known about positions before the given one, which will
+    /// not be asked about again.
+    pub(crate) fn forget_before(&mut self, position: u64) {
+        if let SourceInner::Compressed(reader) = &mut self.inner {
+            reader.get_mut().forget_before(position);
+        }
+    }
+}
+
+impl<R: BufRead> Read for WarcSource<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.inner {
+            SourceInner::Uncompressed(reader) => reader.read(buf),
+            SourceInner::Compressed(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl<R: BufRead> BufRead for WarcSource<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match &mut self.inner {
+            SourceInner::Uncompressed(reader) => reader.fill_buf(),
+            SourceInner::Compressed(reader) => reader.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match &mut self.inner {
+            SourceInner::Uncompressed(reader) => reader.consume(amt),
+            SourceInner::Compressed(reader) => reader.consume(amt),
+        }
+    }
+}
+
+/// Decompresses a stream of any number of gzip members, Zstandard frames or
+/// bzip2 streams, all referred to as members, noting the offset at which each
+/// begins in both the compressed and decompressed streams.
+struct Members<R: BufRead> {
+    compression: WarcCompression,
+    /// Declared ahead of the dictionary so that a decoder referring to the
+    /// dictionary is dropped first.
+    state: MemberState<R>,
+    /// The Zstandard dictionary frames are compressed with, if any, prepared
+    /// once for all of them.
+    dictionary: Option<DecoderDictionary<'static>>,
+    /// The decompressed and compressed offsets at which members begin, in
+    /// order. Once the stream ends, its decompressed and compressed lengths
+    /// are appended.
+    members: Vec<(u64, u64)>,
+    position: u64,
+}
+
+enum MemberState<R: BufRead> {
+    /// Between members, or at the start of the stream.
+    Between(Counted<R>),
+    /// Within a member.
+    Member(MemberDecoder<R>),
+    /// At the end of the stream, or past an error.
+    Ended,
+}
+
+impl<R: BufRead> Members<R> {
+    fn new(reader: R, compression: WarcCompression) -> Self {
+        Self {
+            compression,
+            state: MemberState::Between(Counted {
+                inner: reader,
+                count: 0,
+            }),
+            dictionary: None,
+            members: Vec::new(),
+            position: 0,
+        }
+    }
+
+    fn source_offset(&self, position: u64) -> u64 {
+        let member = self
+            .members
+            .partition_point(|&(member_position, _)| member_position <= position);
+        self.members
+            .get(member.saturating_sub(1))
+            .map_or(0, |&(_, offset)| offset)
+    }
+
+    fn forget_before(&mut self, position: u64) {
+        let member = self
+            .members
+            .partition_point(|&(member_position, _)| member_position <= position);
+        self.members.drain(..member.saturating_sub(1));
+    }
+}
+
+impl<R: BufRead> Read for Members<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match std::mem::replace(&mut self.state, MemberState::Ended) {
+                MemberState::Between(mut counted) => {
+                    self.members.push((self.position, counted.count));
+                    let buf = counted.fill_buf()?;
+                    if buf.is_empty() {
+                        return Ok(0);
+                    }
+                    if self.compression == WarcCompression::Zstd
+                        && buf.starts_with(&ZSTD_DICTIONARY_MAGIC.to_le_bytes())
+                    {
+                        let dictionary = read_dictionary_frame(&mut counted)?;
+                        self.dictionary = Some(DecoderDictionary::copy(&dictionary));
+                        self.state = MemberState::Between(counted);
+                        continue;
+                    }
+                    self.state = MemberState::Member(MemberDecoder::new(
+                        counted,
+                        self.compression,
+                        self.dictionary.as_ref(),
+                    )?);
+                }
+
+                MemberState::Member(mut decoder) => {
+                    let bytes_read = decoder.read(buf)?;
+                    if bytes_read == 0 {
+                        self.state = MemberState::Between(decoder.into_inner());
+                        continue;
+                    }
+                    self.position += bytes_read as u64;
+                    self.state = MemberState::Member(decoder);
+                    return Ok(bytes_read);
+                }
+
+                MemberState::Ended => return Ok(0),
+            }
+        }
+    }
+}
+
+/// The magic number of the skippable frame holding the dictionary of a
+/// `.warc.zst` file.
+const ZSTD_DICTIONARY_MAGIC: u32 = 0x184D2A5D;
+
+/// The magic number of a Zstandard frame.
+const ZSTD_FRAME_MAGIC: u32 = 0xFD2FB528;
+
+/// Reads the skippable frame holding the dictionary of a `.warc.zst` file,
+/// returning the dictionary. The dictionary may itself be compressed.
+fn read_dictionary_frame<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut header = [0; 8];
+    reader.read_exact(&mut header)?;
+    let [_, _, _, _, size @ ..] = header;
+    let size = u32::from_le_bytes(size);
+
+    let mut dictionary = Vec::new();
+    reader.take(size.into()).read_to_end(&mut dictionary)?;
+    if dictionary.len() < size as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated zstd dictionary frame",
+        ));
+    }
+
+    if dictionary.starts_with(&ZSTD_FRAME_MAGIC.to_le_bytes()) {
+        dictionary = zstd::stream::decode_all(dictionary.as_slice())?;
+    }
+    Ok(dictionary)
+}
+
+/// A decoder of a single member, which stops reading where the member ends.
+enum MemberDecoder<R: BufRead> {
+    Gzip(gzip::Decoder<Counted<R>>),
+    Zstd(zstd::stream::read::Decoder<'static, Counted<R>>),
+    Bzip2(BzDecoder<Counted<R>>),
+}
+
+impl<R: BufRead> MemberDecoder<R> {
+    fn new(
+        counted: Counted<R>,
+        compression: WarcCompression,
+        dictionary: Option<&DecoderDictionary<'static>>,
+    ) -> io::Result<Self> {
+        Ok(match compression {
+            WarcCompression::Gzip => MemberDecoder::Gzip(gzip::Decoder::new(counted)?),
+            WarcCompression::Zstd => {
+                let decoder = match dictionary {
+                    Some(dictionary) => {
+                        zstd::stream::read::Decoder::with_prepared_dictionary(counted, dictionary)?
+                    }
+                    None => zstd::stream::read::Decoder::with_buffer(counted)?,
+                };
+                MemberDecoder::Zstd(decoder.single_frame())
+            }
+            WarcCompression::Bzip2 => MemberDecoder::Bzip2(BzDecoder::new(counted)),
+            WarcCompression::Uncompressed => unreachable!("uncompressed input has no members"),
+        })
+    }
+
+    fn into_inner(self) -> Counted<R> {
+        match self {
+            MemberDecoder::Gzip(decoder) => decoder.into_inner(),
+            MemberDecoder::Zstd(decoder) => decoder.finish(),
+            MemberDecoder::Bzip2(decoder) => decoder.into_inner(),
+        }
+    }
+}
+
+impl<R: BufRead> Read for MemberDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            MemberDecoder::Gzip(decoder) => decoder.read(buf),
+            MemberDecoder::Zstd(decoder) => decoder.read(buf),
+            MemberDecoder::Bzip2(decoder) => decoder.read(buf),
+        }
+    }
+}
+
+/// Counts the bytes consumed from a reader.
+struct Counted<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: BufRead> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.inner.read(buf)?;
+        self.count += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+impl<R: BufRead> BufRead for Counted<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.count += amt as u64;
+        self.inner.consume(amt);
+    }
+}
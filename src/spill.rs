@@ -0,0 +1,63 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+    process,
+};
+
+/// Writes record blocks too large to be stored inline to a file in a side
+/// directory, noting where in that file each was written.
+pub(crate) struct BodySpill {
+    path: String,
+    file: BufWriter<File>,
+    threshold: usize,
+    position: u64,
+}
+
+impl BodySpill {
+    /// Creates a spill file in the directory for blocks larger than
+    /// `threshold` bytes. The file is given a name no other file holds, so
+    /// that concurrent readers do not clobber one another.
+    pub(crate) fn create(directory: &Path, threshold: usize) -> io::Result<Self> {
+        let mut attempt = 0;
+        loop {
+            let path = directory.join(format!("bodies-{}-{attempt}.bin", process::id()));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => {
+                    return Ok(Self {
+                        path: path.to_string_lossy().into_owned(),
+                        file: BufWriter::new(file),
+                        threshold,
+                        position: 0,
+                    })
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => attempt += 1,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Returns the path of the spill file.
+    pub(crate) fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the size past which blocks are written to the spill file.
+    pub(crate) fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Copies a block into the spill file as it is read, returning the offset
+    /// in the file it was written at and the number of bytes written.
+    pub(crate) fn write_block(&mut self, block: &mut impl Read) -> io::Result<(u64, u64)> {
+        let spilled_at = self.position;
+        let bytes_written = io::copy(block, &mut self.file)?;
+        self.position += bytes_written;
+        Ok((spilled_at, bytes_written))
+    }
+
+    /// Flushes blocks written so far to the spill file.
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}